@@ -35,7 +35,7 @@ async fn fetch_data(id: u32) -> String {
     tokio::time::sleep(Duration::from_millis(500)).await;
 
     // Add a breakpoint here to inspect the task
-    dbug::_internal::async_break_point(file!(), line!(), column!(), task_id);
+    dbug::_internal::async_break_point(file!(), line!(), column!(), "fetch_data", task_id);
 
     let result = format!("Data for id: {}", id);
 
@@ -80,7 +80,7 @@ async fn process_data(data: String) -> String {
     tokio::time::sleep(Duration::from_millis(300)).await;
 
     // Add a breakpoint here to inspect the task
-    dbug::_internal::async_break_point(file!(), line!(), column!(), task_id);
+    dbug::_internal::async_break_point(file!(), line!(), column!(), "process_data", task_id);
 
     let processed = format!("Processed: {}", data);
 
@@ -36,7 +36,13 @@ async fn process_values(values: Vec<i32>) -> i32 {
         // Only break when index is even and value is greater than 10
         // Replace async_break_when! with manual implementation
         if index % 2 == 0 && *value > 10 {
-            dbug::_internal::async_break_point(file!(), line!(), column!(), task_id);
+            dbug::_internal::async_break_point(
+                file!(),
+                line!(),
+                column!(),
+                "process_values",
+                task_id,
+            );
         }
 
         println!("Processing value: {} at index {}", value, index);
@@ -1,6 +1,7 @@
 use dbug::prelude::*;
 
 // A macro to create a debug guard in a macro-friendly way
+#[cfg(feature = "use_manual_instrumentation")]
 macro_rules! with_debug_guard {
     ($func_name:expr, $body:block) => {{
         struct _DbugGuard<'a> {
@@ -25,7 +26,7 @@ macro_rules! with_debug_guard {
 // First try with the dbug macro, but with a fallback to manual instrumentation
 // just in case the macro fails at compile time
 #[cfg(not(feature = "use_manual_instrumentation"))]
-#[dbug]
+#[dbug(args, ret)]
 fn factorial(n: u64) -> u64 {
     if n <= 1 {
         // Use a macro for breakpoints
@@ -45,12 +46,12 @@ fn factorial(n: u64) -> u64 {
 fn factorial(n: u64) -> u64 {
     with_debug_guard!("factorial", {
         if n <= 1 {
-            dbug::_internal::break_point(file!(), line!(), 0);
+            dbug::_internal::break_point(file!(), line!(), 0, "factorial");
             println!("Base case: n = {}", n);
             return 1;
         } else {
             let result = n * factorial(n - 1);
-            dbug::_internal::break_point(file!(), line!(), 0);
+            dbug::_internal::break_point(file!(), line!(), 0, "factorial");
             println!("Recursive case: n = {}, result = {}", n, result);
             return result;
         }
@@ -81,7 +82,7 @@ fn fibonacci(n: u64) -> u64 {
             println!("Base case: n = {}", n);
             return n;
         } else {
-            dbug::_internal::break_point(file!(), line!(), 0);
+            dbug::_internal::break_point(file!(), line!(), 0, "fibonacci");
             println!("Calculating fibonacci({})...", n);
             let result = fibonacci(n - 1) + fibonacci(n - 2);
             println!("fibonacci({}) = {}", n, result);
@@ -1,73 +1,880 @@
 use proc_macro::TokenStream;
+use proc_macro2::TokenTree;
 use quote::{quote, ToTokens};
-use syn::{parse_macro_input, parse_quote, Block, Expr, ItemFn, Stmt};
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    parse_macro_input, parse_quote, parse_quote_spanned, Attribute, Block, Data, DeriveInput, Expr,
+    Fields, FnArg, Ident, ImplItem, Item, ItemFn, ItemImpl, ItemMod, LitStr, Meta, Pat, PatIdent,
+    PatType, PathSegment, ReturnType, Signature, Stmt, Token, Type, TypeImplTrait,
+};
+
+/// Options accepted by `#[dbug(...)]`
+#[derive(Default, Clone, Copy)]
+struct DbugOptions {
+    /// Register each argument as a variable on entry
+    args: bool,
+    /// Capture the return value and report it when the function exits
+    ret: bool,
+    /// Register every variable bound by a `let` in the body
+    locals: bool,
+    /// Leave the item uninstrumented
+    skip: bool,
+}
+
+impl Parse for DbugOptions {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut options = DbugOptions::default();
+
+        for option in Punctuated::<Ident, Token![,]>::parse_terminated(input)? {
+            match option.to_string().as_str() {
+                "args" => options.args = true,
+                "ret" => options.ret = true,
+                "locals" => options.locals = true,
+                "skip" => options.skip = true,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        &option,
+                        format!(
+                            "unknown dbug option `{}`, expected `args`, `ret`, `locals` or `skip`",
+                            option
+                        ),
+                    ))
+                }
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Whether the macros instrument code, rather than leaving it as written
+///
+/// Instrumentation is on with the `dbug` feature, or in builds made by
+/// `dbug build`, which set `DBUG_BUILD`.
+fn instrumentation_enabled() -> bool {
+    cfg!(feature = "dbug")
+        || matches!(
+            std::env::var("DBUG_BUILD").as_deref(),
+            Ok(value) if !value.is_empty() && value != "0"
+        )
+}
+
+/// An item that makes cargo rebuild the crate when `DBUG_BUILD` changes
+///
+/// Cargo doesn't see the macros read it, but it does track `option_env!`.
+fn track_build_env() -> proc_macro2::TokenStream {
+    quote! {
+        const _: ::core::option::Option<&str> = ::core::option_env!("DBUG_BUILD");
+    }
+}
+
+/// What a debugging macro expands to: `code` when instrumentation is on
+///
+/// Otherwise `uses` is type-checked but never run, so the macro's
+/// arguments still count as used and compile errors don't depend on the
+/// build.
+fn gated(
+    code: impl FnOnce() -> proc_macro2::TokenStream,
+    uses: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let track = track_build_env();
+    if instrumentation_enabled() {
+        let code = code();
+        quote! {
+            {
+                #track
+                #code
+            }
+        }
+    } else {
+        quote! {
+            {
+                #track
+                if false {
+                    #uses
+                }
+            }
+        }
+    }
+}
 
 /// Marks a function for debugging with dbug
 ///
 /// This macro processes a function and inserts instrumentation code
 /// to enable debugging capabilities.
 ///
+/// It can also be put on an `impl` block or an inline `mod`, which
+/// instruments every function inside. Functions are reported by their
+/// full path, e.g. `my_crate::parser::Parser::parse`. Methods of generic
+/// types include the type's arguments, e.g. `my_crate::Cache<K, V>::get`.
+///
+/// Options:
+///
+/// - `args` registers each argument as a variable on entry, like `register_var!`
+/// - `ret` reports the returned value when the function exits
+/// - `locals` registers the variables bound by every `let` in the body,
+///   including pattern and `mut` bindings, right after the assignment
+/// - `skip` leaves an item inside an instrumented `impl` or `mod` alone
+///
+/// Every statement of the body gets a checkpoint, so the debugger can step
+/// through the function line by line.
+///
+/// Outside `dbug build` and without the `dbug` feature, functions are left
+/// as written.
+///
+/// `args` and `locals` capture values with `DbugInspect` when they
+/// implement it, and with `Debug` otherwise; `ret` needs `Debug`. Values
+/// that have neither are shown by their type name.
+/// `ret` is not supported on async functions or functions returning
+/// `impl Trait`; inside an `impl` or `mod` such functions are reported
+/// without a value. An item's own `#[dbug(...)]` replaces the options of
+/// the block around it.
+///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use dbug::prelude::*;
 ///
-/// #[dbug]
+/// #[dbug(args, ret)]
 /// fn my_function(x: i32) -> i32 {
 ///     let y = x * 2;
 ///     dbug::break_here!();  // A debug point
 ///     y + 10
 /// }
+///
+/// # struct Parser;
+/// # struct Ast;
+/// #[dbug]
+/// impl Parser {
+///     fn parse(&mut self) -> Ast {
+///         // ...
+/// #       Ast
+///     }
+///
+///     #[dbug(skip)]
+///     fn peek(&self) -> Option<char> {
+///         // ...
+/// #       None
+///     }
+/// }
 /// ```
 #[proc_macro_attribute]
-pub fn dbug(_attr: TokenStream, item: TokenStream) -> TokenStream {
-    // Parse the input function
-    let result = parse_macro_input!(item as ItemFn);
+pub fn dbug(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let options = parse_macro_input!(attr as DbugOptions);
+    let mut item = parse_macro_input!(item as Item);
 
-    // Get function details
-    let mut input_fn = result;
-    let fn_name = &input_fn.sig.ident;
-    let fn_name_str = fn_name.to_string();
+    if options.skip {
+        return item.to_token_stream().into();
+    }
+
+    let result = match &mut item {
+        Item::Fn(item_fn) => {
+            let name = item_fn.sig.ident.to_string();
+            instrument_fn(&item_fn.sig, &mut item_fn.block, &name, options, true)
+        }
+        Item::Impl(item_impl) => instrument_impl(item_impl, options),
+        Item::Mod(item_mod) => instrument_mod(item_mod, options),
+        _ => Err(syn::Error::new_spanned(
+            &item,
+            "dbug can only be used on functions, impl blocks and inline modules",
+        )),
+    };
+
+    match result {
+        Ok(()) => item.to_token_stream().into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Take an item's own `#[dbug(...)]` attribute off it and parse its options
+fn take_own_options(attrs: &mut Vec<Attribute>) -> syn::Result<Option<DbugOptions>> {
+    let Some(index) = attrs.iter().position(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "dbug")
+    }) else {
+        return Ok(None);
+    };
+
+    let attr = attrs.remove(index);
+    match &attr.meta {
+        Meta::Path(_) => Ok(Some(DbugOptions::default())),
+        _ => attr.parse_args().map(Some),
+    }
+}
+
+/// Instrument every function of an `impl` block
+fn instrument_impl(item_impl: &mut ItemImpl, options: DbugOptions) -> syn::Result<()> {
+    // `Foo<u8>` and `Foo<u16>` are told apart by their generic arguments
+    let type_name = match item_impl.self_ty.as_ref() {
+        Type::Path(type_path) => match type_path.path.segments.last() {
+            Some(segment) => type_display_name(segment),
+            None => type_path.to_token_stream().to_string(),
+        },
+        other => other.to_token_stream().to_string(),
+    };
+
+    for impl_item in &mut item_impl.items {
+        let ImplItem::Fn(method) = impl_item else {
+            continue;
+        };
+
+        let (options, explicit) = match take_own_options(&mut method.attrs)? {
+            Some(own) => (own, true),
+            None => (options, false),
+        };
+        if options.skip || !explicit && is_excluded(&method.sig, &method.attrs) {
+            continue;
+        }
+
+        let name = format!("{}::{}", type_name, method.sig.ident);
+        instrument_fn(&method.sig, &mut method.block, &name, options, explicit)?;
+    }
+
+    Ok(())
+}
+
+/// How a type is written, e.g. `Cache<K, V>`, without the spaces tokenizing adds
+fn type_display_name(segment: &PathSegment) -> String {
+    let mut name = segment.to_token_stream().to_string();
+    for (spaced, tight) in [
+        (" < ", "<"),
+        (" <", "<"),
+        ("< ", "<"),
+        (" >", ">"),
+        (" ,", ","),
+        (" :: ", "::"),
+        ("& ", "&"),
+    ] {
+        name = name.replace(spaced, tight);
+    }
+    name
+}
+
+/// Whether a function inside an instrumented block is left alone without a `skip`
+///
+/// `const fn`s can't run the instrumentation, and `#[dbug_async]` functions
+/// are instrumented by their own attribute.
+fn is_excluded(sig: &Signature, attrs: &[Attribute]) -> bool {
+    sig.constness.is_some()
+        || attrs.iter().any(|attr| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "dbug_async")
+        })
+}
+
+/// Instrument every function and `impl` block of an inline module
+fn instrument_mod(item_mod: &mut ItemMod, options: DbugOptions) -> syn::Result<()> {
+    let Some((_, items)) = &mut item_mod.content else {
+        return Err(syn::Error::new_spanned(
+            &*item_mod,
+            "dbug can only instrument inline modules; put #[dbug] inside the module file instead",
+        ));
+    };
+
+    for item in items {
+        let attrs = match item {
+            Item::Fn(item_fn) => &mut item_fn.attrs,
+            Item::Impl(item_impl) => &mut item_impl.attrs,
+            Item::Mod(item_mod) => &mut item_mod.attrs,
+            _ => continue,
+        };
+        let (options, explicit) = match take_own_options(attrs)? {
+            Some(own) => (own, true),
+            None => (options, false),
+        };
+        if options.skip {
+            continue;
+        }
+
+        match item {
+            Item::Fn(item_fn) if explicit || !is_excluded(&item_fn.sig, &item_fn.attrs) => {
+                let name = item_fn.sig.ident.to_string();
+                instrument_fn(&item_fn.sig, &mut item_fn.block, &name, options, explicit)?;
+            }
+            Item::Impl(item_impl) => instrument_impl(item_impl, options)?,
+            Item::Mod(item_mod) => instrument_mod(item_mod, options)?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Wrap a function body with entry/exit instrumentation
+///
+/// `name` is reported under the module path of the function. With
+/// `explicit` unset the options were inherited from an `impl` or `mod`, and
+/// `ret` is quietly dropped where it can't be honoured.
+fn instrument_fn(
+    sig: &Signature,
+    block: &mut Block,
+    name: &str,
+    options: DbugOptions,
+    explicit: bool,
+) -> syn::Result<()> {
+    // Run the body in a closure so early returns and `?` still pass through
+    // the capture, then hand the value to the guard
+    let capture = match &sig.output {
+        ReturnType::Type(_, return_type) if options.ret => {
+            let impl_trait = find_impl_trait(return_type);
+            if explicit {
+                if let Some(asyncness) = &sig.asyncness {
+                    return Err(syn::Error::new_spanned(
+                        asyncness,
+                        "dbug(ret) cannot capture the return value of an async function",
+                    ));
+                }
+                if let Some(impl_trait) = impl_trait {
+                    return Err(syn::Error::new_spanned(
+                        impl_trait,
+                        "dbug(ret) cannot capture an `impl Trait` return value",
+                    ));
+                }
+            }
+
+            (sig.asyncness.is_none() && impl_trait.is_none()).then_some(return_type)
+        }
+        _ => None,
+    };
+    let track = track_build_env();
+    if !instrumentation_enabled() {
+        block.stmts.insert(0, parse_quote!(#track));
+        return Ok(());
+    }
+
+    let fn_name = quote! { concat!(module_path!(), "::", #name) };
+
+    // Checkpoints go in first so they don't land between a `let` and its registration
+    Checkpoints {
+        function: fn_name.clone(),
+    }
+    .visit_block_mut(block);
+    EnclosingFunction {
+        function: fn_name.clone(),
+    }
+    .visit_block_mut(block);
+    if options.locals {
+        LocalTracer.visit_block_mut(block);
+    }
+
+    // Register the arguments that are plain bindings; patterns are skipped
+    let register_args: Vec<_> = if options.args {
+        sig.inputs
+            .iter()
+            .filter_map(|arg| match arg {
+                FnArg::Typed(PatType { pat, .. }) => match pat.as_ref() {
+                    Pat::Ident(pat_ident) => Some(
+                        VarEntry::variable(&pat_ident.ident, pat_ident.mutability.is_some())
+                            .expand(),
+                    ),
+                    _ => None,
+                },
+                FnArg::Receiver(_) => None,
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let body = match capture {
+        Some(return_type) => quote! {
+            let _dbug_return: #return_type = (|| -> #return_type #block)();
+            // Formatting can be costly, so only do it when someone will see it
+            if ::dbug::_internal::hooks_enabled() {
+                _guard.return_value = Some(format!("{:?}", &_dbug_return));
+            }
+            _dbug_return
+        },
+        None => block.to_token_stream(),
+    };
 
     // Add exit instrumentation using a guard pattern with Drop
     // to ensure it's called on all exit paths, including early returns and panics
-    let block = &input_fn.block;
+    *block = parse_quote! {{
+        #track
 
-    let new_block: Block = parse_quote! {{
         // Create a guard struct to handle function exit
         struct _DbugGuard<'a> {
             fn_name: &'a str,
+            return_value: Option<String>,
         }
 
         impl<'a> Drop for _DbugGuard<'a> {
             fn drop(&mut self) {
-                ::dbug::_internal::exit_function(self.fn_name);
+                ::dbug::_internal::exit_function_returning(
+                    self.fn_name,
+                    self.return_value.as_deref(),
+                );
             }
         }
 
         // Create the guard - will be dropped when the function exits
-        let _guard = _DbugGuard { fn_name: #fn_name_str };
+        #[allow(unused_mut)]
+        let mut _guard = _DbugGuard { fn_name: #fn_name, return_value: None };
 
         // Notify function entry
-        ::dbug::_internal::enter_function(#fn_name_str);
+        ::dbug::_internal::enter_function(#fn_name);
+
+        #(#register_args)*
 
         // Original function body continues here
-        #block
+        #body
     }};
 
-    // Replace the function block with our instrumented block
-    input_fn.block = Box::new(new_block);
+    Ok(())
+}
 
-    // Convert back to TokenStream
-    let output = input_fn.to_token_stream();
-    output.into()
+/// Macros that stop on their own, so their statements need no checkpoint
+const BREAK_MACROS: &[&str] = &[
+    "break_here",
+    "break_when",
+    "async_break_here",
+    "async_break_when",
+];
+
+/// Whether `mac` is one of the `BREAK_MACROS`
+fn is_break_macro(mac: &syn::Macro) -> bool {
+    mac.path
+        .segments
+        .last()
+        .is_some_and(|segment| BREAK_MACROS.iter().any(|name| segment.ident == name))
+}
+
+/// `VisitMut` methods that leave const contexts alone
+///
+/// Array lengths, const blocks and const generic arguments are evaluated at
+/// compile time, so they can't call into the runtime.
+macro_rules! skip_const_contexts {
+    () => {
+        fn visit_expr_const_mut(&mut self, _expr: &mut syn::ExprConst) {}
+        fn visit_type_array_mut(&mut self, _ty: &mut syn::TypeArray) {}
+        fn visit_generic_argument_mut(&mut self, _arg: &mut syn::GenericArgument) {}
+
+        fn visit_expr_repeat_mut(&mut self, expr: &mut syn::ExprRepeat) {
+            // Only the repeated value runs; the length is a constant
+            self.visit_expr_mut(&mut expr.expr);
+        }
+    };
+}
+
+/// Puts a stepping checkpoint before every statement
+struct Checkpoints {
+    /// Path of the function, reported when a checkpoint stops
+    function: proc_macro2::TokenStream,
+}
+
+impl Checkpoints {
+    fn needs_checkpoint(stmt: &Stmt) -> bool {
+        match stmt {
+            Stmt::Item(_) => false,
+            Stmt::Macro(stmt_macro) => !is_break_macro(&stmt_macro.mac),
+            Stmt::Expr(Expr::Macro(expr_macro), _) => !is_break_macro(&expr_macro.mac),
+            _ => true,
+        }
+    }
+}
+
+impl VisitMut for Checkpoints {
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        visit_mut::visit_block_mut(self, block);
+
+        for stmt in std::mem::take(&mut block.stmts) {
+            if Self::needs_checkpoint(&stmt) {
+                // Spanned at the statement so `line!()` is the statement's line
+                let span = stmt.span();
+                let function = &self.function;
+                block.stmts.push(parse_quote_spanned! {span=>
+                    ::dbug::_internal::checkpoint(file!(), line!(), column!(), #function);
+                });
+            }
+            block.stmts.push(stmt);
+        }
+    }
+
+    // Nested functions are not part of this function
+    fn visit_item_mut(&mut self, _item: &mut Item) {}
+
+    skip_const_contexts!();
+}
+
+/// Tells the break macros and `#[break_at]` in a function body which function they are in
+///
+/// The path goes in front of the macro's arguments as `@function = <path>,`,
+/// which `parse_enclosing_function` reads back.
+struct EnclosingFunction {
+    function: proc_macro2::TokenStream,
+}
+
+impl VisitMut for EnclosingFunction {
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        if is_break_macro(mac) {
+            let function = &self.function;
+            let tokens = &mac.tokens;
+            mac.tokens = quote! { @function = #function, #tokens };
+        }
+    }
+
+    fn visit_attribute_mut(&mut self, attr: &mut Attribute) {
+        let is_break_at = attr
+            .path()
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "break_at");
+        if is_break_at && matches!(attr.meta, Meta::Path(_)) {
+            let path = attr.path();
+            let function = &self.function;
+            attr.meta = parse_quote!(#path(@function = #function,));
+        }
+    }
+
+    // Nested functions are not part of this function
+    fn visit_item_mut(&mut self, _item: &mut Item) {}
+}
+
+/// Reads the `@function = <path>,` that `EnclosingFunction` adds to a break macro
+///
+/// Breakpoints outside an instrumented function report their module instead.
+fn parse_enclosing_function(input: ParseStream) -> syn::Result<Expr> {
+    if !input.peek(Token![@]) {
+        return Ok(parse_quote!(module_path!()));
+    }
+
+    input.parse::<Token![@]>()?;
+    let key: Ident = input.parse()?;
+    if key != "function" {
+        return Err(syn::Error::new_spanned(&key, "expected `@function = ...`"));
+    }
+    input.parse::<Token![=]>()?;
+    let function = input.parse()?;
+    input.parse::<Token![,]>()?;
+    Ok(function)
+}
+
+/// Records each `.await` of a `#[dbug_async]` body as it is reached
+///
+/// The awaited future is passed through `_internal::awaiting` once it has
+/// been evaluated, so a task that goes on to wait shows what it waits for.
+struct AwaitPoints;
+
+impl VisitMut for AwaitPoints {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        visit_mut::visit_expr_mut(self, expr);
+
+        if let Expr::Await(expr_await) = expr {
+            let base = &expr_await.base;
+            let expression = display_name(base);
+            // Spanned at the await so `line!()` is its line
+            let span = expr_await.span();
+            expr_await.base = parse_quote_spanned! {span=>
+                ::dbug::_internal::awaiting(_dbug_task_id, file!(), line!(), #expression, #base)
+            };
+        }
+    }
+
+    // Async blocks and closures are futures of their own
+    fn visit_expr_async_mut(&mut self, _expr: &mut syn::ExprAsync) {}
+    fn visit_expr_closure_mut(&mut self, _expr: &mut syn::ExprClosure) {}
+
+    // Nested functions are not part of this function
+    fn visit_item_mut(&mut self, _item: &mut Item) {}
+}
+
+/// Registers the bindings of every `let` right after it, for `#[dbug(locals)]`
+struct LocalTracer;
+
+impl VisitMut for LocalTracer {
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        visit_mut::visit_block_mut(self, block);
+
+        for stmt in std::mem::take(&mut block.stmts) {
+            // A `let` without a value has nothing to show yet
+            let registrations: Vec<Stmt> = match &stmt {
+                Stmt::Local(local) if local.init.is_some() => find_bindings(&local.pat)
+                    .into_iter()
+                    .map(|binding| {
+                        let register = register_local(&binding.ident, binding.mutability.is_some());
+                        parse_quote!(#register)
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            block.stmts.push(stmt);
+            block.stmts.extend(registrations);
+        }
+    }
+
+    // Nested functions are not part of this function
+    fn visit_item_mut(&mut self, _item: &mut Item) {}
+
+    skip_const_contexts!();
+}
+
+/// The variables a pattern binds, once each
+fn find_bindings(pat: &Pat) -> Vec<&PatIdent> {
+    struct Finder<'ast>(Vec<&'ast PatIdent>);
+
+    impl<'ast> Visit<'ast> for Finder<'ast> {
+        fn visit_pat_ident(&mut self, pat_ident: &'ast PatIdent) {
+            // Each alternative of an or-pattern binds the same names
+            if !self.0.iter().any(|seen| seen.ident == pat_ident.ident) {
+                self.0.push(pat_ident);
+            }
+            visit::visit_pat_ident(self, pat_ident);
+        }
+
+        // Types and expressions inside a pattern (`let x: [u8; { .. }]`,
+        // `const { .. }`) can hold their own `let`s, which bind nothing here
+        fn visit_type(&mut self, _ty: &'ast Type) {}
+        fn visit_expr(&mut self, _expr: &'ast Expr) {}
+    }
+
+    let mut finder = Finder(Vec::new());
+    finder.visit_pat(pat);
+    finder.0
+}
+
+/// Find an `impl Trait` anywhere inside a type
+fn find_impl_trait(ty: &Type) -> Option<&TypeImplTrait> {
+    struct Finder<'ast>(Option<&'ast TypeImplTrait>);
+
+    impl<'ast> Visit<'ast> for Finder<'ast> {
+        fn visit_type_impl_trait(&mut self, impl_trait: &'ast TypeImplTrait) {
+            self.0.get_or_insert(impl_trait);
+        }
+    }
+
+    let mut finder = Finder(None);
+    finder.visit_type(ty);
+    finder.0
+}
+
+/// Code that registers a local variable, whether or not it implements `Debug`
+fn register_local(name: &Ident, is_mutable: bool) -> proc_macro2::TokenStream {
+    let name_str = name.to_string();
+
+    quote! {
+        {
+            use ::dbug::_internal::{InspectDebug as _, InspectOpaque as _, InspectStructure as _};
+
+            let _dbug_type_name = std::any::type_name_of_val(&#name);
+            let _dbug_value = (&&&::dbug::_internal::Inspect(&#name)).inspect_value();
+
+            let _ = ::dbug::_internal::register_captured(#name_str, _dbug_type_name, _dbug_value, #is_mutable);
+        }
+    }
+}
+
+/// A value to register with the debugger
+struct VarEntry {
+    /// The expression giving the value
+    value: proc_macro2::TokenStream,
+    /// The name shown by the debugger
+    name: String,
+    /// Type name to show instead of the value's own
+    type_name: Option<LitStr>,
+    /// Whether to show the value as mutable
+    is_mutable: bool,
+}
+
+impl VarEntry {
+    /// A plain variable
+    fn variable(name: &Ident, is_mutable: bool) -> Self {
+        Self {
+            value: name.to_token_stream(),
+            name: name.to_string(),
+            type_name: None,
+            is_mutable,
+        }
+    }
+
+    /// Code that registers the value with the debugger
+    ///
+    /// Shared by `register_var!` and `#[dbug(args)]`.
+    fn expand(&self) -> proc_macro2::TokenStream {
+        let VarEntry {
+            value,
+            name,
+            is_mutable,
+            ..
+        } = self;
+        let type_name = match &self.type_name {
+            Some(type_name) => quote! { #type_name },
+            // Get the type of the value using std::any::type_name
+            None => quote! { std::any::type_name_of_val(_dbug_ref) },
+        };
+
+        // Values without `DbugInspect` or `Debug`, like generic arguments, show their type name
+        quote! {
+            {
+                use ::dbug::_internal::{InspectDebug as _, InspectOpaque as _, InspectStructure as _};
+
+                let _dbug_ref = &(#value);
+                let _dbug_type_name = #type_name;
+                let _dbug_value = (&&&::dbug::_internal::Inspect(_dbug_ref)).inspect_value();
+
+                let _ = ::dbug::_internal::register_captured(#name, _dbug_type_name, _dbug_value, #is_mutable);
+            }
+        }
+    }
+}
+
+impl Parse for VarEntry {
+    /// Parse one `register_var!` argument: `[mut] expr [as "name"] [: "Type"]`
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let is_mutable = input.parse::<Option<Token![mut]>>()?.is_some();
+
+        // `as "name"` would be read as a cast, so the expression is parsed from
+        // the tokens before it and the input skips past what it used
+        let value = input.step(|cursor| {
+            let mut tokens = Vec::new();
+            let mut rest = *cursor;
+            while let Some((token, next)) = rest.token_tree() {
+                if matches!(&token, TokenTree::Ident(ident) if ident == "as")
+                    && next.literal().is_some()
+                {
+                    break;
+                }
+                tokens.push(token);
+                rest = next;
+            }
+
+            let (value, unused) = (|input: ParseStream| {
+                let expr: Expr = input.parse()?;
+                let unused: proc_macro2::TokenStream = input.parse()?;
+                Ok((expr, unused.into_iter().count()))
+            })
+            .parse2(tokens.iter().cloned().collect())?;
+
+            let mut rest = *cursor;
+            for _ in 0..tokens.len() - unused {
+                rest = rest.token_tree().map_or(rest, |(_, next)| next);
+            }
+            Ok((value, rest))
+        })?;
+
+        let name = match input.parse::<Option<Token![as]>>()? {
+            Some(_) => input.parse::<LitStr>()?.value(),
+            None => display_name(&value),
+        };
+        let type_name = match input.parse::<Option<Token![:]>>()? {
+            Some(_) => Some(input.parse::<LitStr>()?),
+            None => None,
+        };
+
+        Ok(Self {
+            value: value.to_token_stream(),
+            name,
+            type_name,
+            is_mutable,
+        })
+    }
+}
+
+/// How an expression is written, without the spaces tokenizing adds
+fn display_name(expr: &Expr) -> String {
+    let mut name = expr.to_token_stream().to_string();
+    for (spaced, tight) in [
+        (" . ", "."),
+        (" :: ", "::"),
+        (" [", "["),
+        ("[ ", "["),
+        (" ]", "]"),
+        (" (", "("),
+        ("( ", "("),
+        (" )", ")"),
+        ("& ", "&"),
+        ("* ", "*"),
+    ] {
+        name = name.replace(spaced, tight);
+    }
+    name
+}
+
+/// Arguments of `break_here!` and `break_when!`
+#[derive(Default)]
+struct BreakArgs {
+    /// Path of the enclosing function, if known
+    function: Option<Expr>,
+    /// Name shown by the debugger for this breakpoint
+    label: Option<LitStr>,
+    /// Only stop when this is true
+    condition: Option<Expr>,
+}
+
+impl Parse for BreakArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = BreakArgs {
+            function: Some(parse_enclosing_function(input)?),
+            ..BreakArgs::default()
+        };
+
+        while !input.is_empty() {
+            if input.peek(Token![if]) {
+                input.parse::<Token![if]>()?;
+                args.condition = Some(input.parse()?);
+            } else {
+                let key: Ident = input.parse()?;
+                if key != "label" {
+                    return Err(syn::Error::new_spanned(
+                        &key,
+                        "expected `label = \"...\"` or `if <condition>`",
+                    ));
+                }
+                input.parse::<Token![=]>()?;
+                args.label = Some(input.parse()?);
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+impl BreakArgs {
+    /// Code that stops at this breakpoint, when its condition holds
+    fn expand(&self) -> proc_macro2::TokenStream {
+        let function = match &self.function {
+            Some(function) => function.to_token_stream(),
+            None => quote! { module_path!() },
+        };
+        let label = match &self.label {
+            Some(label) => quote! { Some(#label) },
+            None => quote! { None },
+        };
+        let break_point = quote! {
+            ::dbug::_internal::break_point_with_label(file!(), line!(), column!(), #function, #label);
+        };
+
+        match &self.condition {
+            Some(condition) => gated(
+                || quote! { if #condition { #break_point } },
+                quote! { let _: bool = #condition; },
+            ),
+            None => gated(|| break_point, quote! {}),
+        }
+    }
 }
 
 /// A macro for marking a breakpoint in code
 ///
+/// Optionally takes a label, shown by the debugger to tell breakpoints
+/// apart, and a condition: `break_here!(label = "retry", if attempts > 3)`.
+///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use dbug::prelude::*;
 ///
 /// fn my_function() {
@@ -77,21 +884,91 @@ pub fn dbug(_attr: TokenStream, item: TokenStream) -> TokenStream {
 /// }
 /// ```
 #[proc_macro]
-pub fn break_here(_input: TokenStream) -> TokenStream {
-    let output = quote! {
-        {
-            ::dbug::_internal::break_point(file!(), line!(), column!());
+pub fn break_here(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as BreakArgs);
+    args.expand().into()
+}
+
+/// A macro for marking a breakpoint that only stops when a condition is true
+///
+/// Can be given a label too: `break_when!(i > 5, label = "late items")`.
+///
+/// # Example
+///
+/// ```no_run
+/// use dbug::prelude::*;
+///
+/// fn my_function(x: i32) {
+///     for i in 0..10 {
+///         dbug::break_when!(i > 5);  // This will pause execution only when i > 5
+///         println!("i = {}", i);
+///     }
+/// }
+/// ```
+#[proc_macro]
+pub fn break_when(input: TokenStream) -> TokenStream {
+    let parser = |input: ParseStream| {
+        let function = parse_enclosing_function(input)?;
+        let condition: Expr = input.parse()?;
+        let mut args = BreakArgs::default();
+        if input.parse::<Option<Token![,]>>()?.is_some() {
+            args = input.parse()?;
+            if let Some(extra) = &args.condition {
+                return Err(syn::Error::new_spanned(
+                    extra,
+                    "break_when! already has a condition",
+                ));
+            }
         }
+        args.function = Some(function);
+        args.condition = Some(condition);
+        Ok(args)
     };
 
-    output.into()
+    match parser.parse(input) {
+        Ok(args) => args.expand().into(),
+        Err(err) => err.to_compile_error().into(),
+    }
 }
 
-/// Attribute to mark a line of code as a debug point
+/// Send a formatted message to the debugger without stopping
+///
+/// Takes the same arguments as `format!`. The message is only formatted
+/// when a debugger is attached.
 ///
 /// # Example
 ///
+/// ```no_run
+/// use dbug::prelude::*;
+///
+/// fn checkout(user: u32, total: f64) {
+///     dbug::log_point!("user={} total={}", user, total);
+/// }
 /// ```
+#[proc_macro]
+pub fn log_point(input: TokenStream) -> TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    gated(
+        || quote! { ::dbug::_internal::log_point(file!(), line!(), || format!(#input)); },
+        quote! { let _ = format!(#input); },
+    )
+    .into()
+}
+
+/// Attribute to mark a line of code as a debug point
+///
+/// Like `break_here!`, the breakpoint names the `#[dbug]` function it is in,
+/// or its module outside one.
+///
+/// Attributes on statements need the nightly `proc_macro_hygiene` and
+/// `stmt_expr_attributes` features, so this example is not compiled.
+///
+/// # Example
+///
+/// ```ignore
+/// #![feature(proc_macro_hygiene, stmt_expr_attributes)]
+///
 /// fn my_function() {
 ///     let x = 42;
 ///     #[dbug::break_at]
@@ -99,16 +976,23 @@ pub fn break_here(_input: TokenStream) -> TokenStream {
 /// }
 /// ```
 #[proc_macro_attribute]
-pub fn break_at(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn break_at(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !instrumentation_enabled() {
+        return item;
+    }
+
+    let function = match parse_enclosing_function.parse(attr) {
+        Ok(function) => function,
+        Err(err) => return err.to_compile_error().into(),
+    };
     let result = syn::parse::<Stmt>(item.clone());
 
     match result {
         Ok(item_ast) => {
+            // Left unbraced, so a `let` stays in scope for the statements after it
             let output = quote! {
-                {
-                    ::dbug::_internal::break_point(file!(), line!(), column!());
-                    #item_ast
-                }
+                ::dbug::_internal::break_point(file!(), line!(), column!(), #function);
+                #item_ast
             };
 
             output.into()
@@ -121,53 +1005,237 @@ pub fn break_at(_attr: TokenStream, item: TokenStream) -> TokenStream {
     }
 }
 
-/// Register a variable with the debugger
+/// Register variables with the debugger
+///
+/// Takes a comma-separated list of variables, field paths or other
+/// expressions. Each is shown under the expression as written, unless it is
+/// renamed with `as "name"`. A leading `mut` shows it as mutable, and a
+/// trailing `: "Type"` replaces its type name.
 ///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use dbug::prelude::*;
 ///
-/// fn my_function() {
+/// # struct State { count: u32 }
+/// # struct Worker { state: State, buffer: Vec<u8> }
+/// # impl Worker {
+/// fn my_function(&self, items: &[u8]) {
 ///     let x = 42;
 ///     dbug::register_var!(x);  // This will register x with the debugger
+///     dbug::register_var!(x, self.state.count, items[0] as "first");
+///     dbug::register_var!(mut self.buffer as "buffer": "RingBuffer");
 /// }
+/// # }
 /// ```
 #[proc_macro]
 pub fn register_var(input: TokenStream) -> TokenStream {
-    let result = syn::parse::<syn::Ident>(input.clone());
+    let parser = |input: ParseStream| {
+        if input.is_empty() {
+            return Err(input.error("expected at least one variable"));
+        }
+        Punctuated::<VarEntry, Token![,]>::parse_terminated(input)
+    };
 
+    let result = parser.parse(input);
     match result {
-        Ok(var_name) => {
-            let var_name_str = var_name.to_string();
+        Ok(entries) => {
+            let values = entries.iter().map(|entry| &entry.value);
+            gated(
+                || {
+                    let registrations = entries.iter().map(VarEntry::expand);
+                    quote! { #(#registrations)* }
+                },
+                quote! { #(let _ = &(#values);)* },
+            )
+            .into()
+        }
+        Err(err) => {
+            // Output a compile error at the argument we failed to parse
+            let error_message = format!("Error parsing register_var! arguments: {}", err);
+            syn::Error::new(err.span(), error_message)
+                .to_compile_error()
+                .into()
+        }
+    }
+}
 
-            let output = quote! {
-                {
-                    // Get the type of the variable using std::any::type_name
-                    let type_name = std::any::type_name_of_val(&#var_name);
+/// How `#[derive(DbugInspect)]` captures a field
+#[derive(Clone, Copy, PartialEq)]
+enum FieldMode {
+    /// With the field's own `DbugInspect`
+    Inspect,
+    /// As its `Debug` rendering, with `#[inspect(debug)]`
+    Debug,
+    /// Not at all, with `#[inspect(skip)]`
+    Skip,
+}
+
+impl FieldMode {
+    fn from_attrs(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut mode = FieldMode::Inspect;
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("inspect")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    mode = FieldMode::Skip;
+                } else if meta.path.is_ident("debug") {
+                    mode = FieldMode::Debug;
+                } else {
+                    return Err(meta.error("expected `skip` or `debug`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(mode)
+    }
+}
 
-                    // For simplicity, convert to string (in a real implementation,
-                    // this would be more sophisticated)
-                    let value_str = format!("{:?}", #var_name);
+/// A pattern binding the fields of a struct or variant, and code that
+/// collects them into a `HashMap` named `_dbug_fields`
+fn inspect_fields(
+    path: proc_macro2::TokenStream,
+    fields: &Fields,
+) -> syn::Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+    let mut patterns = Vec::new();
+    let mut inserts = Vec::new();
 
-                    // Check if the variable is mutable - this is a simplified approach
-                    // In a full implementation, would need more complex analysis
-                    let is_mutable = false;
+    for (index, field) in fields.iter().enumerate() {
+        let binding = Ident::new(&format!("_dbug_field_{}", index), field.span());
+        let mode = FieldMode::from_attrs(&field.attrs)?;
+        let bound = if mode == FieldMode::Skip {
+            quote! { _ }
+        } else {
+            quote! { #binding }
+        };
+        let name = match &field.ident {
+            Some(ident) => {
+                patterns.push(quote! { #ident: #bound });
+                ident.to_string()
+            }
+            None => {
+                patterns.push(bound);
+                index.to_string()
+            }
+        };
 
-                    ::dbug::_internal::register_variable(#var_name_str, type_name, &value_str, is_mutable);
-                }
-            };
+        let value = match mode {
+            FieldMode::Inspect => quote! {
+                ::dbug::runtime::DbugInspect::to_variable_value(#binding)
+            },
+            FieldMode::Debug => quote! {
+                ::dbug::runtime::VariableValue::from_debug(&format!("{:?}", #binding))
+            },
+            FieldMode::Skip => continue,
+        };
+        inserts.push(quote! {
+            _dbug_fields.insert(#name.to_string(), #value);
+        });
+    }
 
-            output.into()
+    let pattern = match fields {
+        Fields::Named(_) => quote! { #path { #(#patterns),* } },
+        Fields::Unnamed(_) => quote! { #path ( #(#patterns),* ) },
+        Fields::Unit => quote! { #path },
+    };
+    let collect = quote! {
+        #[allow(unused_mut)]
+        let mut _dbug_fields = ::std::collections::HashMap::new();
+        #(#inserts)*
+    };
+
+    Ok((pattern, collect))
+}
+
+fn expand_dbug_inspect(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &input.ident;
+    let type_name = ident.to_string();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, collect) = inspect_fields(quote! { Self }, &data.fields)?;
+            quote! {
+                let #pattern = self;
+                #collect
+                ::dbug::runtime::VariableValue::Struct(_dbug_fields)
+            }
         }
-        Err(err) => {
-            // Output a compile error if we fail to parse
-            let error_message = format!("Error parsing variable name in register_var!: {}", err);
-            let error = quote::quote! {
-                compile_error!(#error_message);
-            };
-            error.into()
+        Data::Enum(data) => {
+            let arms = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let variant_ident = &variant.ident;
+                    let variant_name = variant_ident.to_string();
+                    let (pattern, collect) =
+                        inspect_fields(quote! { Self::#variant_ident }, &variant.fields)?;
+                    Ok(quote! {
+                        #pattern => {
+                            #collect
+                            ::dbug::runtime::VariableValue::new_complex(#type_name, #variant_name, _dbug_fields, None)
+                        }
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            if arms.is_empty() {
+                quote! { match *self {} }
+            } else {
+                quote! {
+                    match self {
+                        #(#arms)*
+                    }
+                }
+            }
+        }
+        Data::Union(data) => {
+            return Err(syn::Error::new_spanned(
+                data.union_token,
+                "DbugInspect can't be derived for unions",
+            ))
+        }
+    };
+
+    // Like the std derives, every type parameter must be inspectable too
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param
+            .bounds
+            .push(parse_quote!(::dbug::runtime::DbugInspect));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::dbug::runtime::DbugInspect for #ident #ty_generics #where_clause {
+            fn to_variable_value(&self) -> ::dbug::runtime::VariableValue {
+                #body
+            }
         }
+    })
+}
+
+/// Derives `DbugInspect`, so the debugger can show a value's structure
+///
+/// Structs are shown field by field and enums by variant. Every field must
+/// implement `DbugInspect`, unless it is marked `#[inspect(debug)]` to show
+/// its `Debug` rendering instead, or `#[inspect(skip)]` to leave it out.
+///
+/// # Example
+///
+/// ```no_run
+/// use dbug::prelude::*;
+///
+/// #[derive(DbugInspect)]
+/// enum Shape {
+///     Circle { radius: f64 },
+///     Polygon(Vec<(f64, f64)>),
+///     Empty,
+/// }
+/// ```
+#[proc_macro_derive(DbugInspect, attributes(inspect))]
+pub fn derive_dbug_inspect(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_dbug_inspect(input) {
+        Ok(output) => output.into(),
+        Err(err) => err.to_compile_error().into(),
     }
 }
 
@@ -178,7 +1246,7 @@ pub fn register_var(input: TokenStream) -> TokenStream {
 ///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use dbug::prelude::*;
 ///
 /// #[dbug_async]
@@ -205,46 +1273,37 @@ pub fn dbug_async(_attr: TokenStream, item: TokenStream) -> TokenStream {
         return TokenStream::from(error.to_compile_error());
     }
 
+    let track = track_build_env();
+    if !instrumentation_enabled() {
+        input_fn.block.stmts.insert(0, parse_quote!(#track));
+        return input_fn.to_token_stream().into();
+    }
+
     // Get function details
-    let fn_name = &input_fn.sig.ident;
-    let fn_name_str = fn_name.to_string();
+    let name = input_fn.sig.ident.to_string();
+    let fn_name = quote! { concat!(module_path!(), "::", #name) };
 
-    // Add exit instrumentation using a guard pattern with Drop
-    // to ensure it's called on all exit paths, including early returns and panics
+    EnclosingFunction {
+        function: fn_name.clone(),
+    }
+    .visit_block_mut(&mut input_fn.block);
+    AwaitPoints.visit_block_mut(&mut input_fn.block);
+
+    // The body runs inside a future that reports every poll, and enters and
+    // exits the function on all paths, including early returns and panics
     let block = &input_fn.block;
 
     let new_block: Block = parse_quote! {{
-        // Create a guard struct to handle function exit
-        struct _DbugAsyncGuard<'a> {
-            fn_name: &'a str,
-            task_id: u64,
-        }
-
-        impl<'a> Drop for _DbugAsyncGuard<'a> {
-            fn drop(&mut self) {
-                ::dbug::_internal::exit_async_function(self.fn_name, self.task_id);
-            }
-        }
+        #track
 
         // Generate a unique task identifier for this async execution
-        let task_id = ::dbug::_internal::generate_async_task_id();
+        let _dbug_task_id = ::dbug::_internal::generate_async_task_id();
 
-        // Create the guard - will be dropped when the function exits
-        let _guard = _DbugAsyncGuard {
-            fn_name: #fn_name_str,
-            task_id
-        };
-
-        // Notify function entry
-        ::dbug::_internal::enter_async_function(#fn_name_str, task_id);
-
-        // Original function body continues here
-        // The async execution will be tracked by the task_id
-        #block
+        ::dbug::_internal::InstrumentedFuture::new(#fn_name, _dbug_task_id, async move #block).await
     }};
 
     // Replace the function block with our instrumented block
-    input_fn.block = Box::new(new_block);
+    *input_fn.block = new_block;
 
     // Convert back to TokenStream
     let output = input_fn.to_token_stream();
@@ -255,7 +1314,7 @@ pub fn dbug_async(_attr: TokenStream, item: TokenStream) -> TokenStream {
 ///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use dbug::prelude::*;
 ///
 /// async fn my_async_function() {
@@ -265,14 +1324,22 @@ pub fn dbug_async(_attr: TokenStream, item: TokenStream) -> TokenStream {
 /// }
 /// ```
 #[proc_macro]
-pub fn async_break_here(_input: TokenStream) -> TokenStream {
-    let output = quote! {
-        {
-            let task_id = ::dbug::_internal::get_current_async_task_id();
-            ::dbug::_internal::async_break_point(file!(), line!(), column!(), task_id);
-        }
+pub fn async_break_here(input: TokenStream) -> TokenStream {
+    let function = match parse_enclosing_function.parse(input) {
+        Ok(function) => function,
+        Err(err) => return err.to_compile_error().into(),
     };
 
+    let output = gated(
+        || {
+            quote! {
+                let task_id = ::dbug::_internal::get_current_async_task_id();
+                ::dbug::_internal::async_break_point(file!(), line!(), column!(), #function, task_id);
+            }
+        },
+        quote! {},
+    );
+
     output.into()
 }
 
@@ -282,7 +1349,7 @@ pub fn async_break_here(_input: TokenStream) -> TokenStream {
 ///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use dbug::prelude::*;
 ///
 /// async fn my_async_function(x: i32) {
@@ -294,16 +1361,23 @@ pub fn async_break_here(_input: TokenStream) -> TokenStream {
 /// ```
 #[proc_macro]
 pub fn async_break_when(input: TokenStream) -> TokenStream {
-    let condition = parse_macro_input!(input as Expr);
+    let parser = |input: ParseStream| Ok((parse_enclosing_function(input)?, input.parse()?));
+    let (function, condition): (Expr, Expr) = match parser.parse(input) {
+        Ok(parsed) => parsed,
+        Err(err) => return err.to_compile_error().into(),
+    };
 
-    let output = quote! {
-        {
-            let task_id = ::dbug::_internal::get_current_async_task_id();
-            if #condition {
-                ::dbug::_internal::async_break_point(file!(), line!(), column!(), task_id);
+    let output = gated(
+        || {
+            quote! {
+                let task_id = ::dbug::_internal::get_current_async_task_id();
+                if #condition {
+                    ::dbug::_internal::async_break_point(file!(), line!(), column!(), #function, task_id);
+                }
             }
-        }
-    };
+        },
+        quote! { let _: bool = #condition; },
+    );
 
     output.into()
 }
@@ -0,0 +1,90 @@
+// Event envelope for debuggee messages
+//
+// Every message the debuggee sends is wrapped in an event recording which
+// thread produced it and when, measured on a monotonic clock from the start
+// of the debugging session. This lets the debugger order events and match
+// function entries and exits per thread.
+
+use super::DebuggerMessage;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Start of the session on this process's monotonic clock
+static SESSION_START: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Next id handed out to a thread that sends its first event
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// Id of the current thread, assigned on first use
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The thread an event came from
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ThreadInfo {
+    /// Id of the thread, unique within the debuggee process
+    pub id: u64,
+    /// Name of the thread, if it has one
+    pub name: Option<String>,
+}
+
+impl ThreadInfo {
+    /// Describe the calling thread
+    pub fn current() -> Self {
+        Self {
+            id: THREAD_ID.with(|id| *id),
+            name: std::thread::current().name().map(str::to_string),
+        }
+    }
+}
+
+/// A message from the debuggee together with the thread and time it was sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebuggerEvent {
+    /// The thread that produced the message
+    pub thread: ThreadInfo,
+    /// Microseconds since the session started
+    pub timestamp_us: u64,
+    /// The message itself
+    pub message: DebuggerMessage,
+}
+
+impl DebuggerEvent {
+    /// Stamp a message with the calling thread and the current session time
+    pub fn new(message: DebuggerMessage) -> Self {
+        Self {
+            thread: ThreadInfo::current(),
+            timestamp_us: session_elapsed().as_micros() as u64,
+            message,
+        }
+    }
+
+    /// The time since the session started at which the event was sent
+    pub fn timestamp(&self) -> Duration {
+        Duration::from_micros(self.timestamp_us)
+    }
+
+    /// Expand batched messages into the individual events they carry, in order
+    pub fn flatten(self) -> Vec<DebuggerEvent> {
+        match self.message {
+            DebuggerMessage::BatchedMessages(events) => events
+                .into_iter()
+                .flat_map(DebuggerEvent::flatten)
+                .collect(),
+            _ => vec![self],
+        }
+    }
+}
+
+/// Time elapsed since the session started
+pub fn session_elapsed() -> Duration {
+    SESSION_START.elapsed()
+}
+
+/// Start the session clock, if it is not running yet
+pub(crate) fn start_session_clock() {
+    Lazy::force(&SESSION_START);
+}
@@ -0,0 +1,177 @@
+// Length-prefixed framing for stream transports
+//
+// Stream sockets carry frames as a `u32` little-endian length followed by the
+// payload. Reads wait for the first byte of a frame up to a timeout, then read
+// the rest of the frame without one. Frames from peers that aren't trusted
+// yet are read under a deadline for the whole frame instead.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// Largest frame we are willing to send or receive
+pub(crate) const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// A connected stream socket that frames can be sent over
+pub(crate) trait FrameStream: Read + Write {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl FrameStream for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl FrameStream for std::os::unix::net::UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        std::os::unix::net::UnixStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        std::os::unix::net::UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Write a single frame to the stream
+pub(crate) fn write_frame<S: FrameStream>(stream: &mut S, frame: &[u8]) -> io::Result<()> {
+    if frame.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("frame of {} bytes exceeds the limit", frame.len()),
+        ));
+    }
+
+    let length = (frame.len() as u32).to_le_bytes();
+    stream.write_all(&length)?;
+    stream.write_all(frame)?;
+    stream.flush()
+}
+
+/// Read a single frame of at most `max_size` bytes, waiting at most `timeout` for one to start
+///
+/// Returns `Ok(None)` if no frame started in time. End of stream is reported
+/// as an `UnexpectedEof` error.
+pub(crate) fn read_frame<S: FrameStream>(
+    stream: &mut S,
+    timeout: Duration,
+    max_size: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    // Wait for the first byte of the length prefix, blocking up to the timeout
+    if timeout.is_zero() {
+        stream.set_nonblocking(true)?;
+    } else {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(timeout))?;
+    }
+
+    let mut length = [0u8; 4];
+    let first = match stream.read(&mut length[..1]) {
+        Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+        Ok(n) => n,
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            return Ok(None)
+        }
+        Err(e) => return Err(e),
+    };
+
+    // A frame has started; read the rest of it without a timeout
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(None)?;
+    stream.read_exact(&mut length[first..])?;
+
+    let size = u32::from_le_bytes(length) as usize;
+    if size > max_size {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the limit", size),
+        ));
+    }
+
+    let mut frame = vec![0u8; size];
+    stream.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+/// Read a single frame of at most `max_size` bytes, all of which must arrive within `timeout`
+///
+/// Returns `Ok(None)` if the frame didn't arrive in time, even if part of it
+/// did, so a peer that stalls mid-frame cannot hold up the reader.
+pub(crate) fn read_frame_within<S: FrameStream>(
+    stream: &mut S,
+    timeout: Duration,
+    max_size: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let deadline = Instant::now() + timeout;
+    stream.set_nonblocking(false)?;
+
+    let mut length = [0u8; 4];
+    if !read_exact_before(stream, &mut length, deadline)? {
+        return Ok(None);
+    }
+
+    let size = u32::from_le_bytes(length) as usize;
+    if size > max_size {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the limit", size),
+        ));
+    }
+
+    let mut frame = vec![0u8; size];
+    if !read_exact_before(stream, &mut frame, deadline)? {
+        return Ok(None);
+    }
+    Ok(Some(frame))
+}
+
+/// Fill `buf` from the stream, returning `false` if `deadline` passes first
+fn read_exact_before<S: FrameStream>(
+    stream: &mut S,
+    mut buf: &mut [u8],
+    deadline: Instant,
+) -> io::Result<bool> {
+    while !buf.is_empty() {
+        // A zero timeout means no timeout at all, so stop before reaching one
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(false);
+        }
+        stream.set_read_timeout(Some(remaining))?;
+
+        match stream.read(buf) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => buf = &mut buf[n..],
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(false)
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// The shortest wait a split-off receiver uses
+///
+/// Non-blocking mode is shared by every handle on a socket, so a receiver
+/// waits briefly instead of switching the sending half to non-blocking.
+pub(crate) const MIN_RECEIVER_WAIT: Duration = Duration::from_millis(1);
+
+/// Whether an I/O error means the other side has gone away
+pub(crate) fn is_disconnect(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::UnexpectedEof
+            | ErrorKind::BrokenPipe
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+    )
+}
@@ -0,0 +1,607 @@
+// Memory-mapped file transport
+//
+// Each direction uses its own file holding a single-producer/single-consumer
+// ring buffer. The header at the start of the file carries the head and tail
+// positions (total bytes ever written and read), so neither side can overwrite
+// a frame the other has not consumed yet.
+//
+// File layout:
+//
+//   offset  0  magic        u64
+//   offset  8  capacity     u64  size of the data region in bytes
+//   offset 16  head         u64  advanced by the writer
+//   offset 24  tail         u64  advanced by the reader
+//   offset 32  dropped      u64  frames discarded because the ring was full
+//   offset 40  next_seq     u64  sequence number of the next frame
+//   offset 48  heartbeat    u64  bumped by the reader every time it polls
+//   offset 56  closed       u64  non-zero once either side closed the channel
+//   offset 64  data region  `capacity` bytes
+//
+// Each frame in the data region is a `u32` payload length and a `u64`
+// sequence number (both little endian) followed by the payload. Frames wrap
+// around the end of the data region.
+//
+// Payloads larger than `MAX_FRAME_SIZE` are split into chunks. The top bits
+// of the length field flag a chunk that is continued by the next frame, or
+// tell the reader to discard a message the writer gave up on half way.
+//
+// Shared files give no signal when the other process dies, so the debugger
+// keeps bumping the heartbeat of the message ring while it reads, and the
+// debuggee treats a heartbeat that stopped changing as a lost debugger. The
+// debuggee times that with its own monotonic clock, so neither a wall clock
+// change nor a difference between the two processes' clocks matters.
+
+use super::transport::{receiver_split, BufferFullPolicy, ChannelRole, FrameReceiver, Transport};
+use super::SessionRendezvous;
+use crate::errors::{DbugError, DbugResult};
+use memmap2::{MmapMut, MmapOptions, MmapRaw};
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Marks a file as a dbug ring buffer ("DBUGRING")
+const RING_MAGIC: u64 = 0x4442_5547_5249_4E47;
+
+// Header field offsets
+const MAGIC_OFFSET: usize = 0;
+const CAPACITY_OFFSET: usize = 8;
+const HEAD_OFFSET: usize = 16;
+const TAIL_OFFSET: usize = 24;
+const DROPPED_OFFSET: usize = 32;
+const NEXT_SEQ_OFFSET: usize = 40;
+const HEARTBEAT_OFFSET: usize = 48;
+const CLOSED_OFFSET: usize = 56;
+
+// Size of the header at the start of each file
+const RING_HEADER_SIZE: usize = 64;
+// Size of the header in front of every frame
+const FRAME_HEADER_SIZE: usize = 12;
+// Size of the data region of each ring
+const RING_CAPACITY: usize = 64 * 1024;
+
+/// The size of each memory-mapped file
+pub(crate) const MMAP_SIZE: usize = RING_HEADER_SIZE + RING_CAPACITY;
+
+/// Largest payload carried by a single frame; larger payloads are chunked
+pub(crate) const MAX_FRAME_SIZE: usize = 8192;
+
+/// Largest payload reassembled from chunks
+pub(crate) const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+// Length field flag: the payload continues in the next frame
+const CHUNK_CONTINUES: u32 = 1 << 31;
+// Length field flag: discard the partially received payload
+const CHUNK_ABORT: u32 = 1 << 30;
+// Length field bits holding the actual chunk length
+const CHUNK_LENGTH_MASK: u32 = CHUNK_ABORT - 1;
+
+// How long to sleep between polls of the inbound ring
+const POLL_INTERVAL_MS: u64 = 1;
+// How long a blocking send waits for the reader to make room
+const BLOCK_TIMEOUT_MS: u64 = 5000;
+// How long the debugger may go without reading before it is considered gone
+const HEARTBEAT_TIMEOUT_MS: u64 = 5000;
+
+/// A single-producer/single-consumer ring buffer in a shared mapping
+///
+/// The other process changes the mapping at any time, so it is only ever
+/// accessed through raw pointers and atomics. One thread of this process
+/// pushes and one pops, which lets the two halves of a transport share it.
+struct RingBuffer {
+    map: MmapRaw,
+    capacity: u64,
+    /// The last heartbeat seen from the reader, and when it was first seen
+    last_beat: Mutex<(u64, Instant)>,
+}
+
+impl RingBuffer {
+    /// Lay out a fresh ring in a newly created mapping
+    fn init(map: MmapMut) -> Self {
+        let map = MmapRaw::from(map);
+        let capacity = (map.len() - RING_HEADER_SIZE) as u64;
+        let ring = Self {
+            map,
+            capacity,
+            last_beat: Mutex::new((0, Instant::now())),
+        };
+
+        ring.counter(CAPACITY_OFFSET)
+            .store(capacity, Ordering::Relaxed);
+        ring.counter(HEAD_OFFSET).store(0, Ordering::Relaxed);
+        ring.counter(TAIL_OFFSET).store(0, Ordering::Relaxed);
+        ring.counter(DROPPED_OFFSET).store(0, Ordering::Relaxed);
+        ring.counter(NEXT_SEQ_OFFSET).store(0, Ordering::Relaxed);
+        ring.counter(CLOSED_OFFSET).store(0, Ordering::Relaxed);
+        ring.counter(HEARTBEAT_OFFSET).store(0, Ordering::Relaxed);
+        ring.counter(MAGIC_OFFSET)
+            .store(RING_MAGIC, Ordering::Release);
+
+        ring
+    }
+
+    /// Open a ring laid out by the other side
+    fn open(map: MmapMut, path: &Path) -> DbugResult<Self> {
+        if map.len() < RING_HEADER_SIZE {
+            return Err(DbugError::CommunicationError(format!(
+                "Channel file {} is too small",
+                path.display()
+            )));
+        }
+
+        let ring = Self {
+            capacity: (map.len() - RING_HEADER_SIZE) as u64,
+            map: MmapRaw::from(map),
+            last_beat: Mutex::new((0, Instant::now())),
+        };
+
+        if ring.counter(MAGIC_OFFSET).load(Ordering::Acquire) != RING_MAGIC
+            || ring.counter(CAPACITY_OFFSET).load(Ordering::Relaxed) != ring.capacity
+        {
+            return Err(DbugError::CommunicationError(format!(
+                "Channel file {} is not a dbug ring buffer",
+                path.display()
+            )));
+        }
+
+        Ok(ring)
+    }
+
+    /// Access a header field shared with the other process
+    fn counter(&self, offset: usize) -> &AtomicU64 {
+        // The mapping is page aligned and every header field is 8-byte aligned
+        unsafe { &*(self.map.as_ptr().add(offset) as *const AtomicU64) }
+    }
+
+    /// Number of frames discarded because the ring was full
+    fn dropped(&self) -> u64 {
+        self.counter(DROPPED_OFFSET).load(Ordering::Relaxed)
+    }
+
+    /// Record that the reader is still polling this ring
+    fn beat(&self) {
+        self.counter(HEARTBEAT_OFFSET)
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Whether the heartbeat has not changed for longer than `timeout_ms`
+    fn reader_stale(&self, timeout_ms: u64) -> bool {
+        let beat = self.counter(HEARTBEAT_OFFSET).load(Ordering::Relaxed);
+        let mut last_beat = self
+            .last_beat
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if last_beat.0 != beat {
+            *last_beat = (beat, Instant::now());
+            return false;
+        }
+        last_beat.1.elapsed() > Duration::from_millis(timeout_ms)
+    }
+
+    /// Mark the channel as closed for the other side
+    fn mark_closed(&self) {
+        self.counter(CLOSED_OFFSET).store(1, Ordering::Release);
+    }
+
+    /// Whether either side closed the channel
+    fn is_closed(&self) -> bool {
+        self.counter(CLOSED_OFFSET).load(Ordering::Acquire) != 0
+    }
+
+    /// Number of bytes the writer can append right now
+    fn free_space(&self) -> u64 {
+        let head = self.counter(HEAD_OFFSET).load(Ordering::Relaxed);
+        let tail = self.counter(TAIL_OFFSET).load(Ordering::Acquire);
+        self.capacity - (head - tail)
+    }
+
+    /// Append a frame, returning `false` if there is not enough room
+    fn try_push(&self, payload: &[u8], flags: u32) -> bool {
+        let frame_size = (FRAME_HEADER_SIZE + payload.len()) as u64;
+        if self.free_space() < frame_size {
+            return false;
+        }
+
+        let head = self.counter(HEAD_OFFSET).load(Ordering::Relaxed);
+
+        let seq = self
+            .counter(NEXT_SEQ_OFFSET)
+            .fetch_add(1, Ordering::Relaxed);
+
+        let mut header = [0u8; FRAME_HEADER_SIZE];
+        header[..4].copy_from_slice(&(payload.len() as u32 | flags).to_le_bytes());
+        header[4..].copy_from_slice(&seq.to_le_bytes());
+
+        self.write_at(head, &header);
+        self.write_at(head + FRAME_HEADER_SIZE as u64, payload);
+
+        // Publish the frame only once it has been written completely
+        self.counter(HEAD_OFFSET)
+            .store(head + frame_size, Ordering::Release);
+        true
+    }
+
+    /// Remove the oldest frame, returning its sequence number, flags and payload
+    fn pop(&self) -> DbugResult<Option<(u64, u32, Vec<u8>)>> {
+        let tail = self.counter(TAIL_OFFSET).load(Ordering::Relaxed);
+        let head = self.counter(HEAD_OFFSET).load(Ordering::Acquire);
+
+        if head == tail {
+            return Ok(None);
+        }
+
+        let header = self.read_at(tail, FRAME_HEADER_SIZE);
+        let word = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let flags = word & !CHUNK_LENGTH_MASK;
+        let length = (word & CHUNK_LENGTH_MASK) as u64;
+        let seq = u64::from_le_bytes(header[4..].try_into().unwrap());
+
+        if length + FRAME_HEADER_SIZE as u64 > head - tail {
+            return Err(DbugError::CommunicationError(format!(
+                "Corrupt frame in ring buffer (length {} at position {})",
+                length, tail
+            )));
+        }
+
+        let payload = self.read_at(tail + FRAME_HEADER_SIZE as u64, length as usize);
+
+        // Hand the space back to the writer
+        self.counter(TAIL_OFFSET)
+            .store(tail + FRAME_HEADER_SIZE as u64 + length, Ordering::Release);
+
+        Ok(Some((seq, flags, payload)))
+    }
+
+    /// Copy bytes into the data region, wrapping around its end
+    fn write_at(&self, position: u64, bytes: &[u8]) {
+        let offset = (position % self.capacity) as usize;
+        let first = bytes.len().min(self.capacity as usize - offset);
+
+        // Both copies stay inside the data region, which only the writer
+        // touches between the tail and the capacity
+        unsafe {
+            let data = self.map.as_mut_ptr().add(RING_HEADER_SIZE);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), data.add(offset), first);
+            std::ptr::copy_nonoverlapping(bytes[first..].as_ptr(), data, bytes.len() - first);
+        }
+    }
+
+    /// Copy bytes out of the data region, wrapping around its end
+    fn read_at(&self, position: u64, length: usize) -> Vec<u8> {
+        let offset = (position % self.capacity) as usize;
+        let first = length.min(self.capacity as usize - offset);
+
+        let mut bytes = vec![0u8; length];
+        // Both copies stay inside the data region, and the writer leaves
+        // published frames alone until the tail moves past them
+        unsafe {
+            let data = self.map.as_ptr().add(RING_HEADER_SIZE);
+            std::ptr::copy_nonoverlapping(data.add(offset), bytes.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(data, bytes[first..].as_mut_ptr(), length - first);
+        }
+        bytes
+    }
+}
+
+/// Reassembles payloads from the frames of an inbound ring
+struct RingReader {
+    ring: Arc<RingBuffer>,
+    /// Sequence number expected for the next frame
+    next_seq: u64,
+    /// Chunks of a payload received so far
+    partial: Vec<u8>,
+}
+
+impl RingReader {
+    fn new(ring: Arc<RingBuffer>) -> Self {
+        Self {
+            ring,
+            next_seq: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Take the next complete payload, if all of its chunks have arrived
+    fn next_payload(&mut self) -> DbugResult<Option<Vec<u8>>> {
+        while let Some((seq, flags, chunk)) = self.ring.pop()? {
+            if seq != self.next_seq {
+                return Err(DbugError::CommunicationError(format!(
+                    "Out-of-order frame in ring buffer (expected {}, got {})",
+                    self.next_seq, seq
+                )));
+            }
+            self.next_seq = seq + 1;
+
+            if flags & CHUNK_ABORT != 0 {
+                self.partial.clear();
+                continue;
+            }
+
+            if self.partial.len() + chunk.len() > MAX_MESSAGE_SIZE {
+                self.partial.clear();
+                return Err(DbugError::CommunicationError(format!(
+                    "Chunked message exceeds the {} byte limit",
+                    MAX_MESSAGE_SIZE
+                )));
+            }
+            self.partial.extend_from_slice(&chunk);
+
+            if flags & CHUNK_CONTINUES == 0 {
+                return Ok(Some(std::mem::take(&mut self.partial)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Wait at most `timeout` for the next payload, failing once `peer_gone` says so
+    fn recv(
+        &mut self,
+        timeout: Duration,
+        peer_gone: impl Fn() -> bool,
+    ) -> DbugResult<Option<Vec<u8>>> {
+        let start_time = Instant::now();
+
+        loop {
+            self.ring.beat();
+
+            if let Some(payload) = self.next_payload()? {
+                return Ok(Some(payload));
+            }
+
+            // Frames sent before the other side went away are still delivered
+            if peer_gone() {
+                return Err(DbugError::Disconnected);
+            }
+
+            if start_time.elapsed() >= timeout {
+                return Ok(None);
+            }
+
+            // No frame yet, wait a bit and try again
+            std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        }
+    }
+}
+
+/// Whether the other side closed the channel or, for the debuggee, stopped reading
+fn peer_gone(role: ChannelRole, inbound: &RingBuffer, outbound: &RingBuffer) -> bool {
+    if inbound.is_closed() || outbound.is_closed() {
+        return true;
+    }
+
+    // Only the debugger reads continuously, so only its heartbeat is meaningful
+    role == ChannelRole::Debuggee && outbound.reader_stale(HEARTBEAT_TIMEOUT_MS)
+}
+
+/// Transport over a pair of memory-mapped ring buffers
+pub struct MmapTransport {
+    /// The ring this side writes frames to
+    outbound: Arc<RingBuffer>,
+    /// The ring this side reads frames from
+    inbound: Arc<RingBuffer>,
+    /// Reads the inbound ring, until it is split off
+    reader: Option<RingReader>,
+    /// Which side of the channel this is
+    role: ChannelRole,
+    /// What to do when the outbound ring is full
+    full_policy: BufferFullPolicy,
+    /// Whether an outbound payload was abandoned after some of its chunks
+    abandoned: bool,
+}
+
+/// The receiving half of an [`MmapTransport`]
+pub struct MmapReceiver {
+    reader: RingReader,
+    outbound: Arc<RingBuffer>,
+    role: ChannelRole,
+}
+
+impl MmapTransport {
+    /// Create the session files (debugger side)
+    pub fn create(rendezvous: &SessionRendezvous) -> DbugResult<Self> {
+        let message_ring =
+            RingBuffer::init(map_channel_file(&rendezvous.message_file_path(), true)?);
+        let response_ring =
+            RingBuffer::init(map_channel_file(&rendezvous.response_file_path(), true)?);
+
+        Ok(Self::new(
+            message_ring,
+            response_ring,
+            ChannelRole::Debugger,
+            rendezvous.buffer_full,
+        ))
+    }
+
+    /// Attach to existing session files (debuggee side)
+    pub fn attach(rendezvous: &SessionRendezvous) -> DbugResult<Self> {
+        let message_path = rendezvous.message_file_path();
+        let response_path = rendezvous.response_file_path();
+        let message_ring =
+            RingBuffer::open(map_channel_file(&message_path, false)?, &message_path)?;
+        let response_ring =
+            RingBuffer::open(map_channel_file(&response_path, false)?, &response_path)?;
+
+        Ok(Self::new(
+            message_ring,
+            response_ring,
+            ChannelRole::Debuggee,
+            rendezvous.buffer_full,
+        ))
+    }
+
+    fn new(
+        message_ring: RingBuffer,
+        response_ring: RingBuffer,
+        role: ChannelRole,
+        full_policy: BufferFullPolicy,
+    ) -> Self {
+        // Messages flow from the debuggee to the debugger, responses the other way
+        let (outbound, inbound) = match role {
+            ChannelRole::Debuggee => (message_ring, response_ring),
+            ChannelRole::Debugger => (response_ring, message_ring),
+        };
+
+        let inbound = Arc::new(inbound);
+        Self {
+            outbound: Arc::new(outbound),
+            reader: Some(RingReader::new(Arc::clone(&inbound))),
+            inbound,
+            role,
+            full_policy,
+            abandoned: false,
+        }
+    }
+
+    /// Number of frames this side dropped because the outbound ring was full
+    pub fn dropped_outbound(&self) -> u64 {
+        self.outbound.dropped()
+    }
+
+    /// Whether the other side closed the channel or, for the debuggee, stopped reading
+    fn peer_gone(&self) -> bool {
+        peer_gone(self.role, &self.inbound, &self.outbound)
+    }
+
+    /// Push a single chunk, waiting for the reader to make room
+    fn push_chunk(&mut self, chunk: &[u8], flags: u32) -> DbugResult<()> {
+        let start_time = Instant::now();
+        while !self.outbound.try_push(chunk, flags) {
+            if self.peer_gone() {
+                return Err(DbugError::Disconnected);
+            }
+            if start_time.elapsed() >= Duration::from_millis(BLOCK_TIMEOUT_MS) {
+                return Err(DbugError::CommunicationError(
+                    "Message buffer full: the other side is not reading".to_string(),
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        }
+
+        Ok(())
+    }
+}
+
+impl Transport for MmapTransport {
+    fn send(&mut self, frame: &[u8]) -> DbugResult<()> {
+        if self.peer_gone() {
+            return Err(DbugError::Disconnected);
+        }
+
+        if frame.len() > MAX_MESSAGE_SIZE {
+            return Err(DbugError::CommunicationError(format!(
+                "Message too large to send: {} bytes",
+                frame.len()
+            )));
+        }
+
+        // Tell the reader to forget the chunks of a payload we gave up on
+        if self.abandoned {
+            self.push_chunk(&[], CHUNK_ABORT)?;
+            self.abandoned = false;
+        }
+
+        let chunks: Vec<&[u8]> = if frame.is_empty() {
+            vec![frame]
+        } else {
+            frame.chunks(MAX_FRAME_SIZE).collect()
+        };
+
+        // Dropping is all or nothing, so the reader never sees half a payload
+        if self.full_policy == BufferFullPolicy::Drop {
+            let needed: usize = chunks
+                .iter()
+                .map(|chunk| FRAME_HEADER_SIZE + chunk.len())
+                .sum();
+            if self.outbound.free_space() < needed as u64 {
+                self.outbound
+                    .counter(DROPPED_OFFSET)
+                    .fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+
+        // Shared mappings are coherent between processes, so no msync is needed
+        let last = chunks.len() - 1;
+        for (index, chunk) in chunks.iter().enumerate() {
+            let flags = if index < last { CHUNK_CONTINUES } else { 0 };
+            if let Err(e) = self.push_chunk(chunk, flags) {
+                self.abandoned = index > 0;
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn recv(&mut self, timeout: Duration) -> DbugResult<Option<Vec<u8>>> {
+        let (role, inbound, outbound) = (self.role, &self.inbound, &self.outbound);
+        let reader = self.reader.as_mut().ok_or_else(receiver_split)?;
+        reader.recv(timeout, || peer_gone(role, inbound, outbound))
+    }
+
+    fn split_receiver(&mut self) -> DbugResult<Box<dyn FrameReceiver>> {
+        let reader = self.reader.take().ok_or_else(receiver_split)?;
+        Ok(Box::new(MmapReceiver {
+            reader,
+            outbound: Arc::clone(&self.outbound),
+            role: self.role,
+        }))
+    }
+
+    fn is_connected(&self) -> bool {
+        !self.peer_gone()
+    }
+
+    fn dropped_frames(&self) -> u64 {
+        self.inbound.dropped()
+    }
+
+    fn close(&mut self) -> DbugResult<()> {
+        self.outbound.mark_closed();
+        self.inbound.mark_closed();
+        Ok(())
+    }
+}
+
+impl FrameReceiver for MmapReceiver {
+    fn recv(&mut self, timeout: Duration) -> DbugResult<Option<Vec<u8>>> {
+        let (role, outbound) = (self.role, &self.outbound);
+        let inbound = Arc::clone(&self.reader.ring);
+        self.reader
+            .recv(timeout, || peer_gone(role, &inbound, outbound))
+    }
+}
+
+/// Open (or create) a channel file and map it into memory
+fn map_channel_file(path: &Path, create: bool) -> DbugResult<MmapMut> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(create)
+        .truncate(create)
+        .open(path)
+        .map_err(|e| {
+            DbugError::CommunicationError(format!(
+                "Failed to open channel file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+    if create {
+        file.set_len(MMAP_SIZE as u64).map_err(|e| {
+            DbugError::CommunicationError(format!("Failed to set channel file size: {}", e))
+        })?;
+    }
+
+    unsafe { MmapOptions::new().map_mut(&file) }.map_err(|e| {
+        DbugError::CommunicationError(format!(
+            "Failed to create memory map for {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
@@ -0,0 +1,1751 @@
+// Communication module for the dbug debugger
+//
+// This module provides communication mechanisms between the debugger and the instrumented code.
+
+pub mod event;
+mod framing;
+pub mod mmap;
+pub mod protocol;
+pub mod pump;
+pub mod tcp;
+pub mod trace;
+pub mod transport;
+#[cfg(unix)]
+pub mod unix_socket;
+
+pub use event::{DebuggerEvent, ThreadInfo};
+pub use protocol::{Capability, Hello, Welcome, PROTOCOL_VERSION};
+pub use pump::{ChannelEvent, EventPump};
+pub use trace::{Trace, TraceRecorder};
+pub use transport::{BufferFullPolicy, ChannelRole, FrameReceiver, Transport, TransportKind};
+
+use crate::errors::{DbugError, DbugResult};
+use crate::runtime::VariableValue;
+use mmap::MmapTransport;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, remove_file};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Maximum time to wait for a response from the debugger during the handshake;
+// a stopped program waits until it is resumed or the debugger goes away
+const RESPONSE_TIMEOUT_MS: u64 = 5000;
+// How long the program sleeps, without holding the channel, between checks for a response
+const RESPONSE_POLL_INTERVAL_MS: u64 = 1;
+// Maximum message batch size before forced flush
+const MAX_BATCH_SIZE: usize = 10;
+
+/// Environment variable used to pass the session directory to the debuggee
+pub const SESSION_DIR_ENV: &str = "DBUG_SESSION_DIR";
+/// Environment variable used to pass the session token to the debuggee
+pub const SESSION_TOKEN_ENV: &str = "DBUG_SESSION_TOKEN";
+/// Environment variable used to pass the transport kind to the debuggee
+pub const SESSION_TRANSPORT_ENV: &str = "DBUG_TRANSPORT";
+/// Environment variable used to pass the buffer-full policy to the debuggee
+pub const SESSION_BUFFER_FULL_ENV: &str = "DBUG_BUFFER_FULL";
+/// Environment variable used to pass the TCP listen address to the debuggee
+pub const SESSION_ADDRESS_ENV: &str = "DBUG_ADDRESS";
+/// Environment variable that tells a program it is run by the debugger
+pub const ENABLED_ENV: &str = "DBUG_ENABLED";
+/// Environment variable that prints every instrumentation hook when set to `trace`
+pub const LOG_ENV: &str = "DBUG_LOG";
+
+// File names inside a session directory
+const MESSAGE_FILE_NAME: &str = "message.json";
+const RESPONSE_FILE_NAME: &str = "response.json";
+const TOKEN_FILE_NAME: &str = "token";
+const SOCKET_FILE_NAME: &str = "socket";
+
+/// Identifies the channel shared by a debugger and the process it debugs
+///
+/// The debugger creates the session directory and token before spawning the
+/// debuggee, and passes both down through the environment so the runtime can
+/// attach to the same files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionRendezvous {
+    /// Directory holding the channel files
+    pub dir: PathBuf,
+    /// Token identifying this session
+    pub token: String,
+    /// The transport both sides use to talk to each other
+    pub transport: TransportKind,
+    /// What the sender does when the channel buffer is full
+    pub buffer_full: BufferFullPolicy,
+    /// Address the debuggee listens on for the TCP transport
+    pub address: Option<String>,
+}
+
+impl SessionRendezvous {
+    /// Create a new session directory with a fresh token
+    ///
+    /// On Unix only the current user can open the directory, which keeps
+    /// other users away from the channel files and the socket inside it.
+    pub fn create(transport: TransportKind, buffer_full: BufferFullPolicy) -> DbugResult<Self> {
+        let token = generate_session_token();
+        let dir = env::temp_dir().join(format!("dbug_session_{}", token));
+
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&dir).map_err(|e| {
+            DbugError::CommunicationError(format!("Failed to create session directory: {}", e))
+        })?;
+
+        fs::write(dir.join(TOKEN_FILE_NAME), &token).map_err(|e| {
+            DbugError::CommunicationError(format!("Failed to write session token: {}", e))
+        })?;
+
+        Ok(Self {
+            dir,
+            token,
+            transport,
+            buffer_full,
+            address: None,
+        })
+    }
+
+    /// Have the debuggee listen for a debugger on `address` over TCP
+    pub fn listening_on(mut self, address: &str) -> Self {
+        self.transport = TransportKind::Tcp;
+        self.address = Some(address.to_string());
+        self
+    }
+
+    /// Read the rendezvous passed down by the debugger, if any
+    pub fn from_env() -> Option<Self> {
+        let dir = env::var_os(SESSION_DIR_ENV)?;
+        let token = env::var(SESSION_TOKEN_ENV).ok()?;
+        let transport = env::var(SESSION_TRANSPORT_ENV)
+            .ok()
+            .and_then(|kind| kind.parse().ok())
+            .unwrap_or_default();
+        let buffer_full = env::var(SESSION_BUFFER_FULL_ENV)
+            .ok()
+            .and_then(|policy| policy.parse().ok())
+            .unwrap_or_default();
+        let address = env::var(SESSION_ADDRESS_ENV).ok();
+
+        Some(Self {
+            dir: PathBuf::from(dir),
+            token,
+            transport,
+            buffer_full,
+            address,
+        })
+    }
+
+    /// Pass the rendezvous to a child process through its environment
+    pub fn apply_to_command(&self, command: &mut Command) {
+        command
+            .env(SESSION_DIR_ENV, &self.dir)
+            .env(SESSION_TOKEN_ENV, &self.token)
+            .env(SESSION_TRANSPORT_ENV, self.transport.to_string())
+            .env(SESSION_BUFFER_FULL_ENV, self.buffer_full.to_string());
+
+        if let Some(address) = &self.address {
+            command.env(SESSION_ADDRESS_ENV, address);
+        }
+    }
+
+    /// Check that the session directory belongs to this token
+    pub fn verify(&self) -> DbugResult<()> {
+        let stored = fs::read_to_string(self.dir.join(TOKEN_FILE_NAME)).map_err(|e| {
+            DbugError::CommunicationError(format!("Failed to read session token: {}", e))
+        })?;
+
+        if stored.trim() != self.token {
+            return Err(DbugError::CommunicationError(format!(
+                "Session token mismatch for {}",
+                self.dir.display()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Path to the file carrying messages to the debugger
+    pub fn message_file_path(&self) -> PathBuf {
+        self.dir.join(MESSAGE_FILE_NAME)
+    }
+
+    /// Path to the file carrying responses from the debugger
+    pub fn response_file_path(&self) -> PathBuf {
+        self.dir.join(RESPONSE_FILE_NAME)
+    }
+
+    /// Path to the Unix domain socket for this session
+    pub fn socket_path(&self) -> PathBuf {
+        self.dir.join(SOCKET_FILE_NAME)
+    }
+
+    /// Remove the session directory and the channel files in it
+    pub fn remove(&self) -> DbugResult<()> {
+        for path in [
+            self.message_file_path(),
+            self.response_file_path(),
+            self.socket_path(),
+        ] {
+            if path.exists() {
+                remove_file(&path).map_err(|e| {
+                    DbugError::CommunicationError(format!(
+                        "Failed to remove {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+            }
+        }
+
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir).map_err(|e| {
+                DbugError::CommunicationError(format!("Failed to remove session directory: {}", e))
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Generate a token that is unique enough to tell sessions apart
+fn generate_session_token() -> String {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.write_u128(nanos);
+    format!("{:016x}", hasher.finish())
+}
+
+/// A message from the instrumented code to the debugger
+///
+/// Messages travel wrapped in a [`DebuggerEvent`] recording the thread and
+/// time they were sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DebuggerMessage {
+    /// The debuggee introduces itself; always the first message of a session
+    Hello(Hello),
+    /// A breakpoint has been hit
+    BreakpointHit {
+        /// The file where the breakpoint was hit
+        file: String,
+        /// The line where the breakpoint was hit
+        line: u32,
+        /// The column where the breakpoint was hit
+        column: u32,
+        /// The function where the breakpoint was hit
+        function: String,
+        /// The label given to `break_here!`, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+        /// The `#[dbug]` functions the thread is inside of, innermost first
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        call_stack: Vec<String>,
+    },
+    /// A function has been entered
+    FunctionEntered {
+        /// The name of the function
+        function: String,
+        /// The file containing the function
+        file: String,
+        /// The line where the function starts
+        line: u32,
+    },
+    /// A function has been exited
+    FunctionExited {
+        /// The name of the function
+        function: String,
+        /// The value the function returned, when it was captured with `#[dbug(ret)]`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        return_value: Option<String>,
+    },
+    /// A variable has been created or modified
+    VariableChanged {
+        /// The name of the variable
+        name: String,
+        /// The type of the variable
+        type_name: String,
+        /// The value of the variable as a string
+        value: String,
+        /// Whether the variable is mutable
+        is_mutable: bool,
+        /// Fields, variants and elements of a value captured with `DbugInspect`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        structure: Option<VariableValue>,
+    },
+    /// Multiple events batched together for efficiency
+    BatchedMessages(Vec<DebuggerEvent>),
+    /// Expression result
+    ExpressionResult {
+        /// The expression
+        expression: String,
+        /// The result
+        result: String,
+    },
+    /// Execution stopped because the debugger asked it to pause
+    Paused {
+        /// The instrumentation point where execution stopped
+        location: String,
+    },
+    /// A logpoint fired; execution carried on
+    Log {
+        /// The file of the logpoint
+        file: String,
+        /// The line of the logpoint
+        line: u32,
+        /// The formatted message
+        message: String,
+    },
+    /// Async debug message types
+    AsyncTaskCreated {
+        function_name: String,
+        task_id: u64,
+        parent_id: Option<u64>,
+    },
+    AsyncTaskStateChanged {
+        task_id: u64,
+        old_state: String,
+        new_state: String,
+        /// The `.await` a waiting task is blocked on
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        awaiting: Option<String>,
+    },
+    AsyncFunctionEntered {
+        function_name: String,
+        task_id: u64,
+    },
+    AsyncFunctionExited {
+        function_name: String,
+        task_id: u64,
+    },
+    AsyncBreakPoint {
+        file: String,
+        line: u32,
+        column: u32,
+        task_id: u64,
+        /// The function, or the module if the breakpoint is outside a `#[dbug_async]` function
+        #[serde(default)]
+        function: String,
+    },
+}
+
+/// A response from the debugger to the instrumented code
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DebuggerResponse {
+    /// The debugger accepted the debuggee's `Hello`
+    Welcome(Welcome),
+    /// The debugger refused the debuggee's `Hello`
+    Refused {
+        /// Why the connection was refused
+        reason: String,
+    },
+    /// Continue execution
+    Continue,
+    /// Step over to the next line
+    StepOver,
+    /// Step into a function
+    StepInto,
+    /// Step out of the current function
+    StepOut,
+    /// Evaluate an expression and return the result
+    Evaluate {
+        /// The expression to evaluate
+        expression: String,
+    },
+    /// Stop at the next instrumentation point
+    Pause,
+    /// Stop debugging and let the program run on its own
+    Detach {
+        /// Whether to forget all breakpoints as well
+        clear_breakpoints: bool,
+    },
+    /// Terminate the program
+    Kill,
+    /// Replace the debuggee's breakpoint table
+    SetBreakpoints {
+        /// Every breakpoint the debugger knows about
+        breakpoints: Vec<BreakpointSpec>,
+    },
+}
+
+/// A breakpoint as the debugger describes it to the debuggee
+///
+/// Debug points at locations without a spec always stop. A spec can disable
+/// the debug point at its location or make it conditional.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BreakpointSpec {
+    /// The file containing the debug point, as reported by `file!()`
+    pub file: String,
+    /// The line of the debug point
+    pub line: u32,
+    /// Whether the debug point stops at all
+    pub enabled: bool,
+    /// Only stop when this expression evaluates to true
+    pub condition: Option<String>,
+    /// Only stop when the hit count matches, e.g. "= 3", "> 10" or "% 2"
+    pub hit_count: Option<String>,
+    /// Log this message instead of stopping; `{expression}` is replaced by its value
+    #[serde(default)]
+    pub log_message: Option<String>,
+}
+
+impl BreakpointSpec {
+    /// An unconditional breakpoint at a location
+    pub fn new(file: &str, line: u32, enabled: bool) -> Self {
+        Self {
+            file: file.to_string(),
+            line,
+            enabled,
+            condition: None,
+            hit_count: None,
+            log_message: None,
+        }
+    }
+
+    /// A logpoint at a location, e.g. with the message `"x is {x}"`
+    pub fn logpoint(file: &str, line: u32, message: &str) -> Self {
+        Self {
+            log_message: Some(message.to_string()),
+            ..Self::new(file, line, true)
+        }
+    }
+}
+
+/// Global communication channel instance
+static COMMUNICATION_CHANNEL: Lazy<Arc<Mutex<CommunicationChannel>>> = Lazy::new(|| {
+    Arc::new(Mutex::new(CommunicationChannel::new().unwrap_or_else(
+        |e| {
+            eprintln!("[DBUG] Failed to attach to debugger session: {}", e);
+            CommunicationChannel::disconnected()
+        },
+    )))
+});
+
+/// Flag to indicate if a batch flush is in progress
+static BATCH_FLUSH_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// Whether this process completed the handshake with a debugger
+static DEBUGGER_ATTACHED: AtomicBool = AtomicBool::new(false);
+
+/// Session time in microseconds when the channel was last polled for a request
+static LAST_PAUSE_POLL_US: AtomicU64 = AtomicU64::new(0);
+
+// Minimum time between two polls for a request
+const PAUSE_POLL_INTERVAL_US: u64 = 2000;
+
+// Exit code of a program killed by the debugger, as if by SIGKILL
+const KILLED_EXIT_CODE: i32 = 137;
+
+/// Handles communication between the debugger and the instrumented code
+pub struct CommunicationChannel {
+    /// The session this channel belongs to, if connected
+    rendezvous: Option<SessionRendezvous>,
+    /// Which side of the session this channel is on
+    role: ChannelRole,
+    /// Whether the channel is active
+    active: bool,
+    /// The transport carrying frames to the other side
+    transport: Option<Box<dyn Transport>>,
+    /// The peer's `Hello` (debugger side) once the handshake is done
+    peer: Option<Hello>,
+    /// The `Welcome` sent or received once the handshake is done
+    welcome: Option<Welcome>,
+    /// Message queue for batching
+    message_queue: VecDeque<DebuggerEvent>,
+    /// Last flush time
+    last_flush: Instant,
+    /// The breakpoint table pushed to the debuggee (debugger side)
+    breakpoints: Vec<BreakpointSpec>,
+}
+
+impl CommunicationChannel {
+    /// Create the channel for the current process
+    ///
+    /// A debuggee attaches to the session named in its environment. Without
+    /// one, the channel stays disconnected and every operation is a no-op.
+    pub fn new() -> DbugResult<Self> {
+        match SessionRendezvous::from_env() {
+            Some(rendezvous) => Self::attach(&rendezvous),
+            None => Ok(Self::disconnected()),
+        }
+    }
+
+    /// Create a channel that is not connected to any debugger
+    pub fn disconnected() -> Self {
+        Self {
+            rendezvous: None,
+            role: ChannelRole::Debuggee,
+            active: false,
+            transport: None,
+            peer: None,
+            welcome: None,
+            message_queue: VecDeque::with_capacity(MAX_BATCH_SIZE),
+            last_flush: Instant::now(),
+            breakpoints: Vec::new(),
+        }
+    }
+
+    /// Create the channel for a new session (debugger side)
+    pub fn create(rendezvous: &SessionRendezvous) -> DbugResult<Self> {
+        let transport: Box<dyn Transport> = match rendezvous.transport {
+            TransportKind::Mmap => Box::new(MmapTransport::create(rendezvous)?),
+            #[cfg(unix)]
+            TransportKind::UnixSocket => {
+                Box::new(unix_socket::UnixSocketTransport::listen(rendezvous)?)
+            }
+            TransportKind::Tcp => {
+                return Err(DbugError::CommunicationError(
+                    "The debugger connects to a TCP session with `dbug attach`".to_string(),
+                ))
+            }
+            #[cfg(not(unix))]
+            kind => return Err(transport::unsupported(kind)),
+        };
+
+        Ok(Self::with_transport(
+            rendezvous,
+            ChannelRole::Debugger,
+            transport,
+        ))
+    }
+
+    /// Attach to the channel of an existing session (debuggee side)
+    pub fn attach(rendezvous: &SessionRendezvous) -> DbugResult<Self> {
+        rendezvous.verify()?;
+
+        // Event timestamps count from the moment the debuggee joins the session
+        event::start_session_clock();
+
+        let transport: Box<dyn Transport> = match rendezvous.transport {
+            TransportKind::Mmap => Box::new(MmapTransport::attach(rendezvous)?),
+            #[cfg(unix)]
+            TransportKind::UnixSocket => {
+                Box::new(unix_socket::UnixSocketTransport::connect(rendezvous)?)
+            }
+            TransportKind::Tcp => {
+                let address = rendezvous.address.as_deref().ok_or_else(|| {
+                    DbugError::CommunicationError("No TCP listen address given".to_string())
+                })?;
+                eprintln!("[DBUG] Waiting for a debugger on {}", address);
+                Box::new(tcp::TcpTransport::listen(address, &rendezvous.token)?)
+            }
+            #[cfg(not(unix))]
+            kind => return Err(transport::unsupported(kind)),
+        };
+
+        Ok(Self::with_transport(
+            rendezvous,
+            ChannelRole::Debuggee,
+            transport,
+        ))
+    }
+
+    /// Connect to a debuggee listening on `address` over TCP (debugger side)
+    pub fn connect(address: &str, token: &str) -> DbugResult<Self> {
+        let transport = tcp::TcpTransport::connect(address, token)?;
+
+        Ok(Self::from_transport(
+            None,
+            ChannelRole::Debugger,
+            Box::new(transport),
+        ))
+    }
+
+    /// Build an active channel around an already-connected transport
+    pub fn with_transport(
+        rendezvous: &SessionRendezvous,
+        role: ChannelRole,
+        transport: Box<dyn Transport>,
+    ) -> Self {
+        Self::from_transport(Some(rendezvous.clone()), role, transport)
+    }
+
+    fn from_transport(
+        rendezvous: Option<SessionRendezvous>,
+        role: ChannelRole,
+        transport: Box<dyn Transport>,
+    ) -> Self {
+        Self {
+            rendezvous,
+            role,
+            active: true,
+            transport: Some(transport),
+            peer: None,
+            welcome: None,
+            message_queue: VecDeque::with_capacity(MAX_BATCH_SIZE),
+            last_flush: Instant::now(),
+            breakpoints: Vec::new(),
+        }
+    }
+
+    /// Whether the channel is connected to a session
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// The session this channel belongs to, if connected
+    pub fn rendezvous(&self) -> Option<&SessionRendezvous> {
+        self.rendezvous.as_ref()
+    }
+
+    /// Which side of the session this channel is on
+    pub fn role(&self) -> ChannelRole {
+        self.role
+    }
+
+    /// The debuggee's `Hello`, once it has been accepted (debugger side)
+    pub fn peer(&self) -> Option<&Hello> {
+        self.peer.as_ref()
+    }
+
+    /// The `Welcome` that completed the handshake, if it has happened
+    pub fn welcome(&self) -> Option<&Welcome> {
+        self.welcome.as_ref()
+    }
+
+    /// Whether both sides agreed on supporting a capability
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.welcome
+            .as_ref()
+            .map(|welcome| welcome.capabilities.contains(&capability))
+            .unwrap_or(false)
+    }
+
+    /// Number of messages the other side dropped because the channel was full
+    pub fn dropped_messages(&self) -> u64 {
+        self.transport
+            .as_ref()
+            .map(|transport| transport.dropped_frames())
+            .unwrap_or(0)
+    }
+
+    /// Whether the other side is still known to be connected
+    pub fn is_connected(&self) -> bool {
+        self.active
+            && self
+                .transport
+                .as_ref()
+                .map(|transport| transport.is_connected())
+                .unwrap_or(false)
+    }
+
+    /// Queue a message to be sent to the debugger
+    pub fn queue_message(&mut self, message: DebuggerMessage) -> DbugResult<()> {
+        if !self.active {
+            return Ok(());
+        }
+
+        // Stamp the message now, so batching does not skew its time
+        self.message_queue.push_back(DebuggerEvent::new(message));
+
+        // Flush if we've reached the batch size or if it's a breakpoint (which needs immediate attention)
+        let should_flush = self.message_queue.len() >= MAX_BATCH_SIZE
+            || self
+                .message_queue
+                .iter()
+                .any(|e| matches!(e.message, DebuggerMessage::BreakpointHit { .. }))
+            || self.last_flush.elapsed() > Duration::from_millis(100);
+
+        if should_flush {
+            self.flush_message_queue()?;
+        }
+
+        Ok(())
+    }
+
+    /// Flush the message queue
+    fn flush_message_queue(&mut self) -> DbugResult<()> {
+        if self.message_queue.is_empty() || !self.active {
+            return Ok(());
+        }
+
+        // Set the flag to avoid recursive flushes
+        if BATCH_FLUSH_IN_PROGRESS.swap(true, Ordering::SeqCst) {
+            // Another flush is in progress, skip this one
+            return Ok(());
+        }
+
+        let events: Vec<_> = self.message_queue.drain(..).collect();
+
+        // If there's only one event, send it directly, otherwise batch them
+        let event_to_send = if events.len() == 1 {
+            events.into_iter().next().unwrap()
+        } else {
+            DebuggerEvent::new(DebuggerMessage::BatchedMessages(events))
+        };
+
+        let result = self.send_event_internal(&event_to_send);
+
+        // Reset the flag
+        BATCH_FLUSH_IN_PROGRESS.store(false, Ordering::SeqCst);
+
+        // Update the last flush time
+        self.last_flush = Instant::now();
+
+        result
+    }
+
+    /// Internal method to send an event to the debugger
+    fn send_event_internal(&mut self, event: &DebuggerEvent) -> DbugResult<()> {
+        if !self.active {
+            return Ok(());
+        }
+
+        let json = serde_json::to_vec(event).map_err(DbugError::JsonParse)?;
+        self.send_frame(&json)
+    }
+
+    /// Send a serialized frame over the transport
+    fn send_frame(&mut self, frame: &[u8]) -> DbugResult<()> {
+        match self.transport.as_mut() {
+            Some(transport) => transport.send(frame),
+            None => Err(DbugError::CommunicationError(
+                "Transport not initialized".into(),
+            )),
+        }
+    }
+
+    /// Wait for a response from the debugger
+    pub fn wait_for_response(&mut self) -> DbugResult<Option<DebuggerResponse>> {
+        self.wait_for_response_within(Duration::from_millis(RESPONSE_TIMEOUT_MS))
+    }
+
+    /// Wait at most `timeout` for a response from the debugger
+    ///
+    /// A zero timeout only takes a response that has already arrived.
+    pub fn wait_for_response_within(
+        &mut self,
+        timeout: Duration,
+    ) -> DbugResult<Option<DebuggerResponse>> {
+        if !self.active {
+            return Ok(None);
+        }
+
+        // Make sure all pending messages are sent
+        self.flush_message_queue()?;
+
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let transport = self
+                .transport
+                .as_mut()
+                .ok_or_else(|| DbugError::CommunicationError("Transport not initialized".into()))?;
+
+            let frame = match transport.recv(remaining)? {
+                Some(frame) => frame,
+                // Timeout reached
+                None if remaining.is_zero() => return Err(DbugError::ResponseTimeout),
+                None => continue,
+            };
+
+            match serde_json::from_slice::<DebuggerResponse>(&frame) {
+                Ok(response) => return Ok(Some(response)),
+                Err(e) => {
+                    // Skip frames we cannot understand and keep waiting
+                    eprintln!("[DBUG] Ignoring malformed response: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Drain pending responses, returning any request to pause, detach or kill (debuggee side)
+    ///
+    /// Detaching and killing take precedence over pausing. Breakpoint tables
+    /// are installed in the runtime on the way. Any other response
+    /// arriving while the program runs has nothing left to answer, so it is
+    /// discarded.
+    pub fn poll_request(&mut self) -> DbugResult<Option<DebuggerResponse>> {
+        if !self.active {
+            return Ok(None);
+        }
+
+        let transport = self
+            .transport
+            .as_mut()
+            .ok_or_else(|| DbugError::CommunicationError("Transport not initialized".into()))?;
+
+        let mut request = None;
+        while let Some(frame) = transport.recv(Duration::ZERO)? {
+            match serde_json::from_slice(&frame) {
+                Ok(response @ (DebuggerResponse::Detach { .. } | DebuggerResponse::Kill)) => {
+                    return Ok(Some(response))
+                }
+                Ok(DebuggerResponse::Pause) => request = Some(DebuggerResponse::Pause),
+                Ok(DebuggerResponse::SetBreakpoints { breakpoints }) => {
+                    crate::runtime::flow_control::install_breakpoints(&breakpoints)
+                }
+                _ => {}
+            }
+        }
+
+        Ok(request)
+    }
+
+    /// Introduce this process to the debugger and wait for its answer (debuggee side)
+    pub fn handshake(&mut self) -> DbugResult<Welcome> {
+        if !self.active {
+            return Err(DbugError::CommunicationError(
+                "Communication channel is not active".to_string(),
+            ));
+        }
+
+        self.send_event_internal(&DebuggerEvent::new(
+            DebuggerMessage::Hello(Hello::current()),
+        ))?;
+
+        let welcome = match self.wait_for_response()? {
+            Some(DebuggerResponse::Welcome(welcome)) => welcome,
+            Some(DebuggerResponse::Refused { reason }) => {
+                return Err(DbugError::CommunicationError(format!(
+                    "Debugger refused the connection: {}",
+                    reason
+                )))
+            }
+            Some(other) => {
+                return Err(DbugError::CommunicationError(format!(
+                    "Expected a Welcome from the debugger, got {:?}",
+                    other
+                )))
+            }
+            None => return Err(DbugError::ResponseTimeout),
+        };
+        self.welcome = Some(welcome.clone());
+
+        // The initial breakpoint table follows right away, before any debug point runs
+        if self.has_capability(Capability::BreakpointSync) {
+            match self.wait_for_response()? {
+                Some(DebuggerResponse::SetBreakpoints { breakpoints }) => {
+                    crate::runtime::flow_control::install_breakpoints(&breakpoints)
+                }
+                Some(other) => {
+                    return Err(DbugError::CommunicationError(format!(
+                        "Expected the breakpoint table from the debugger, got {:?}",
+                        other
+                    )))
+                }
+                None => return Err(DbugError::ResponseTimeout),
+            }
+        }
+
+        Ok(welcome)
+    }
+
+    /// Receive the next event from the debuggee, waiting at most `timeout` (debugger side)
+    ///
+    /// Frames are decoded with [`Self::decode_event`].
+    pub fn recv_event(&mut self, timeout: Duration) -> DbugResult<Option<DebuggerEvent>> {
+        if !self.active {
+            return Ok(None);
+        }
+
+        let transport = self
+            .transport
+            .as_mut()
+            .ok_or_else(|| DbugError::CommunicationError("Transport not initialized".into()))?;
+
+        match transport.recv(timeout)? {
+            Some(frame) => self.decode_event(&frame).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Split off the receiving half of the transport (debugger side)
+    ///
+    /// Lets a reader wait for frames without holding the channel, which
+    /// keeps sending. Frames it receives are passed to [`Self::decode_event`].
+    pub fn split_receiver(&mut self) -> DbugResult<Box<dyn FrameReceiver>> {
+        if !self.active {
+            return Err(DbugError::CommunicationError(
+                "Communication channel is not active".to_string(),
+            ));
+        }
+
+        self.transport
+            .as_mut()
+            .ok_or_else(|| DbugError::CommunicationError("Transport not initialized".into()))?
+            .split_receiver()
+    }
+
+    /// Decode a frame from the debuggee (debugger side)
+    ///
+    /// The first frame of a session must be the debuggee's `Hello`. It is
+    /// answered here, and an incompatible debuggee is refused with an error.
+    pub fn decode_event(&mut self, frame: &[u8]) -> DbugResult<DebuggerEvent> {
+        if self.peer.is_none() {
+            return self.accept_hello(frame);
+        }
+
+        serde_json::from_slice(frame).map_err(DbugError::JsonParse)
+    }
+
+    /// Check the debuggee's `Hello` and answer it with a `Welcome` or a refusal
+    fn accept_hello(&mut self, frame: &[u8]) -> DbugResult<DebuggerEvent> {
+        let negotiated = parse_hello(frame).and_then(|event| match &event.message {
+            DebuggerMessage::Hello(hello) => {
+                Welcome::negotiate(hello).map(|welcome| (event, welcome))
+            }
+            _ => Err(DbugError::CommunicationError(
+                "Expected a Hello from the debuggee".to_string(),
+            )),
+        });
+
+        let (event, welcome) = match negotiated {
+            Ok(negotiated) => negotiated,
+            Err(e) => {
+                let _ = self.send_response(&DebuggerResponse::Refused {
+                    reason: e.to_string(),
+                });
+                return Err(e);
+            }
+        };
+
+        self.send_response(&DebuggerResponse::Welcome(welcome.clone()))?;
+        self.welcome = Some(welcome);
+        if let DebuggerMessage::Hello(hello) = &event.message {
+            self.peer = Some(hello.clone());
+        }
+
+        if self.has_capability(Capability::BreakpointSync) {
+            self.send_response(&DebuggerResponse::SetBreakpoints {
+                breakpoints: self.breakpoints.clone(),
+            })?;
+        }
+        Ok(event)
+    }
+
+    /// The breakpoint table pushed to the debuggee (debugger side)
+    pub fn breakpoints(&self) -> &[BreakpointSpec] {
+        &self.breakpoints
+    }
+
+    /// Replace the breakpoint table and push it to a connected debuggee (debugger side)
+    ///
+    /// A debuggee that connects later receives the table during the handshake,
+    /// and one that has gone away is left alone.
+    pub fn set_breakpoints(&mut self, breakpoints: Vec<BreakpointSpec>) -> DbugResult<()> {
+        self.breakpoints = breakpoints;
+
+        if !self.active || self.peer.is_none() || !self.has_capability(Capability::BreakpointSync) {
+            return Ok(());
+        }
+
+        self.send_response(&DebuggerResponse::SetBreakpoints {
+            breakpoints: self.breakpoints.clone(),
+        })
+    }
+
+    /// Send a response to the instrumented code (debugger side)
+    pub fn send_response(&mut self, response: &DebuggerResponse) -> DbugResult<()> {
+        if !self.active {
+            return Err(DbugError::CommunicationError(
+                "Communication channel is not active".to_string(),
+            ));
+        }
+
+        let json = serde_json::to_vec(response).map_err(|e| {
+            DbugError::CommunicationError(format!("Failed to serialize response: {}", e))
+        })?;
+
+        self.send_frame(&json)
+    }
+
+    /// Close the communication channel
+    pub fn close(&mut self) -> DbugResult<()> {
+        // Flush any remaining messages
+        let _ = self.flush_message_queue();
+
+        self.active = false;
+
+        // Release the transport
+        if let Some(mut transport) = self.transport.take() {
+            let _ = transport.close();
+        }
+
+        // Only the side that created the session removes its files
+        match self.rendezvous.take() {
+            Some(rendezvous) if self.role == ChannelRole::Debugger => rendezvous.remove(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parse the event carrying the `Hello` a session opens with, checking its version first
+fn parse_hello(frame: &[u8]) -> DbugResult<DebuggerEvent> {
+    let version = protocol::peek_hello_version(frame).ok_or_else(|| {
+        DbugError::CommunicationError(
+            "The debuggee did not open with a Hello; it was probably built against an older dbug"
+                .to_string(),
+        )
+    })?;
+    protocol::negotiate_version(version)?;
+
+    Ok(serde_json::from_slice(frame)?)
+}
+
+impl Drop for CommunicationChannel {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// Whether this process is currently attached to a debugger
+///
+/// Cheap enough to check at every instrumentation point.
+pub fn is_attached() -> bool {
+    DEBUGGER_ATTACHED.load(Ordering::Relaxed)
+}
+
+/// Run `f` on the global channel, detaching if the debugger turns out to be gone (debuggee side)
+///
+/// Without a debugger this returns at once, without taking the channel lock.
+fn with_debugger<T: Default>(
+    f: impl FnOnce(&mut CommunicationChannel) -> DbugResult<T>,
+) -> DbugResult<T> {
+    if !is_attached() {
+        return Ok(T::default());
+    }
+
+    let result = match COMMUNICATION_CHANNEL.lock() {
+        Ok(mut channel) => f(&mut channel),
+        Err(_) => {
+            return Err(DbugError::CommunicationError(
+                "Failed to acquire lock on communication channel".into(),
+            ))
+        }
+    };
+
+    match result {
+        Err(DbugError::Disconnected) => {
+            debugger_lost();
+            Ok(T::default())
+        }
+        result => result,
+    }
+}
+
+/// Detach after finding out the debugger has gone away
+fn debugger_lost() {
+    eprintln!("[DBUG] Lost the debugger; detaching");
+    detach(true);
+}
+
+/// Stop talking to the debugger and let the program run on its own (debuggee side)
+///
+/// Instrumentation points become no-ops afterwards.
+pub fn detach(clear_breakpoints: bool) {
+    DEBUGGER_ATTACHED.store(false, Ordering::Relaxed);
+
+    if let Ok(mut channel) = COMMUNICATION_CHANNEL.lock() {
+        let _ = channel.close();
+    }
+
+    // Nobody is left to stop for
+    let _ = crate::runtime::set_continue();
+    if clear_breakpoints {
+        crate::runtime::clear_all_breakpoints();
+    }
+}
+
+/// Queue a message to be sent to the debugger
+pub fn queue_message(message: DebuggerMessage) -> DbugResult<()> {
+    with_debugger(|channel| channel.queue_message(message))
+}
+
+/// Send a message to the debugger immediately
+pub fn send_message(message: DebuggerMessage) -> DbugResult<()> {
+    with_debugger(|channel| {
+        // Queue and force an immediate flush
+        channel.queue_message(message)?;
+        channel.flush_message_queue()
+    })
+}
+
+/// Wait for a response from the debugger
+///
+/// The channel is only locked to check for a response, so other threads can
+/// keep reporting to the debugger while this one waits.
+pub fn wait_for_response() -> DbugResult<Option<DebuggerResponse>> {
+    let deadline = Instant::now() + Duration::from_millis(RESPONSE_TIMEOUT_MS);
+
+    loop {
+        match with_debugger(|channel| channel.wait_for_response_within(Duration::ZERO)) {
+            Err(DbugError::ResponseTimeout) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(RESPONSE_POLL_INTERVAL_MS));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Process a debug point in the code
+pub fn process_debug_point(
+    file: &str,
+    line: u32,
+    column: u32,
+    function: &str,
+    label: Option<&str>,
+) -> DbugResult<()> {
+    // Create the breakpoint hit message
+    let message = DebuggerMessage::BreakpointHit {
+        file: file.to_string(),
+        line,
+        column,
+        function: function.to_string(),
+        label: label.map(str::to_string),
+        call_stack: crate::runtime::flow_control::function_stack(),
+    };
+
+    // Send the message to the debugger
+    send_message(message)?;
+
+    // Stay stopped until the debugger says how to go on, however long that takes
+    wait_for_resume()
+}
+
+/// Act on a pause, detach or kill request the debugger sent while the program was running
+///
+/// This is called at every instrumentation point, so it only looks at the
+/// channel every couple of milliseconds and never waits for the lock.
+/// `location` describes the point for the debugger if the program pauses.
+pub fn poll_debugger<F: FnOnce() -> String>(location: F) -> DbugResult<()> {
+    if !is_attached() {
+        return Ok(());
+    }
+
+    let now = event::session_elapsed().as_micros() as u64;
+    if now.saturating_sub(LAST_PAUSE_POLL_US.load(Ordering::Relaxed)) < PAUSE_POLL_INTERVAL_US {
+        return Ok(());
+    }
+    LAST_PAUSE_POLL_US.store(now, Ordering::Relaxed);
+
+    let request = match COMMUNICATION_CHANNEL.try_lock() {
+        Ok(mut channel) => channel.poll_request(),
+        // Another thread is using the channel; check again next time
+        Err(_) => Ok(None),
+    };
+
+    match request {
+        Ok(Some(DebuggerResponse::Pause)) => {
+            send_message(DebuggerMessage::Paused {
+                location: location(),
+            })?;
+
+            // Stay paused until the debugger resumes us, however long that takes
+            wait_for_resume()
+        }
+        Ok(Some(response)) => {
+            apply_response(response);
+            Ok(())
+        }
+        Ok(None) => Ok(()),
+        Err(DbugError::Disconnected) => {
+            debugger_lost();
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Wait for the debugger to say how to resume execution
+///
+/// Pause requests are ignored, since execution is already stopped, and
+/// breakpoint tables are installed while waiting. Timeouts are ignored too;
+/// only a response or a disconnect ends the wait.
+fn wait_for_resume() -> DbugResult<()> {
+    // Steps the debugger asks for are measured from this stop
+    crate::runtime::flow_control::mark_stop();
+
+    loop {
+        match wait_for_response() {
+            Ok(Some(DebuggerResponse::Pause)) => continue,
+            Ok(Some(DebuggerResponse::SetBreakpoints { breakpoints })) => {
+                // Tables may change while stopped; keep waiting for the verdict
+                crate::runtime::flow_control::install_breakpoints(&breakpoints);
+                continue;
+            }
+            Ok(Some(response)) => {
+                apply_response(response);
+                return Ok(());
+            }
+            Ok(None) => return Ok(()),
+            Err(DbugError::ResponseTimeout) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Act on the debugger's answer to a stop
+fn apply_response(response: DebuggerResponse) {
+    match response {
+        DebuggerResponse::Continue => {
+            // Stop stepping, if we were
+            if let Err(e) = crate::runtime::set_continue() {
+                eprintln!("[DBUG] Error setting continue: {}", e);
+            }
+        }
+        DebuggerResponse::StepOver => {
+            // Set step-over flag in the runtime
+            if let Err(e) = crate::runtime::set_step_over() {
+                eprintln!("[DBUG] Error setting step over: {}", e);
+            }
+        }
+        DebuggerResponse::StepInto => {
+            // Set step-into flag in the runtime
+            if let Err(e) = crate::runtime::set_step_into() {
+                eprintln!("[DBUG] Error setting step into: {}", e);
+            }
+        }
+        DebuggerResponse::StepOut => {
+            // Set step-out flag in the runtime
+            if let Err(e) = crate::runtime::set_step_out() {
+                eprintln!("[DBUG] Error setting step out: {}", e);
+            }
+        }
+        DebuggerResponse::Evaluate { expression } => {
+            // Implement the expression evaluation
+            if let Err(e) = evaluate_expression(&expression) {
+                eprintln!("[DBUG] Error evaluating expression: {}", e);
+            }
+        }
+        DebuggerResponse::Pause => {
+            // Already stopped
+        }
+        DebuggerResponse::SetBreakpoints { breakpoints } => {
+            crate::runtime::flow_control::install_breakpoints(&breakpoints);
+        }
+        DebuggerResponse::Detach { clear_breakpoints } => {
+            eprintln!("[DBUG] Detached from debugger");
+            detach(clear_breakpoints);
+        }
+        DebuggerResponse::Kill => {
+            eprintln!("[DBUG] Killed by debugger");
+            detach(false);
+            std::process::exit(KILLED_EXIT_CODE);
+        }
+        DebuggerResponse::Welcome(_) | DebuggerResponse::Refused { .. } => {
+            // Handshake responses only arrive while attaching
+            eprintln!("[DBUG] Ignoring unexpected handshake response");
+        }
+    }
+}
+
+/// Evaluates an expression in the current context and sends the result back to the debugger
+fn evaluate_expression(expression: &str) -> DbugResult<()> {
+    // Get the current variable scope from the runtime
+    let variables = crate::runtime::get_current_variables()?;
+
+    // Use the runtime's expression evaluator
+    let result = match crate::runtime::evaluate_expression(expression, &variables) {
+        Some(value) => value,
+        None => format!("Could not evaluate expression: {}", expression),
+    };
+
+    // Send the result back to the debugger
+    let message = DebuggerMessage::ExpressionResult {
+        expression: expression.to_string(),
+        result,
+    };
+
+    send_message(message)
+}
+
+/// Notify the debugger that a function has been entered
+pub fn notify_function_entered(function: &str, file: &str, line: u32) -> DbugResult<()> {
+    let message = DebuggerMessage::FunctionEntered {
+        function: function.to_string(),
+        file: file.to_string(),
+        line,
+    };
+
+    queue_message(message)
+}
+
+/// Notify the debugger that a function has been exited
+pub fn notify_function_exited(function: &str, return_value: Option<&str>) -> DbugResult<()> {
+    let message = DebuggerMessage::FunctionExited {
+        function: function.to_string(),
+        return_value: return_value.map(str::to_string),
+    };
+
+    queue_message(message)
+}
+
+/// Send the debugger a logpoint message
+pub fn notify_log(file: &str, line: u32, message: &str) -> DbugResult<()> {
+    let message = DebuggerMessage::Log {
+        file: file.to_string(),
+        line,
+        message: message.to_string(),
+    };
+
+    queue_message(message)
+}
+
+/// Notify the debugger that a variable has been changed
+pub fn notify_variable_changed(
+    name: &str,
+    type_name: &str,
+    value: &str,
+    is_mutable: bool,
+) -> DbugResult<()> {
+    let message = DebuggerMessage::VariableChanged {
+        name: name.to_string(),
+        type_name: type_name.to_string(),
+        value: value.to_string(),
+        is_mutable,
+        structure: None,
+    };
+
+    queue_message(message)
+}
+
+/// Notify the debugger of a variable, along with the structure of its value
+pub fn notify_variable_inspected(
+    name: &str,
+    type_name: &str,
+    value: &VariableValue,
+    is_mutable: bool,
+) -> DbugResult<()> {
+    let message = DebuggerMessage::VariableChanged {
+        name: name.to_string(),
+        type_name: type_name.to_string(),
+        value: value.to_string(),
+        is_mutable,
+        structure: Some(value.clone()),
+    };
+
+    queue_message(message)
+}
+
+/// Notify the debugger that an async task has been created
+pub fn notify_async_task_created(
+    function_name: &str,
+    task_id: u64,
+    parent_id: Option<u64>,
+) -> DbugResult<()> {
+    let message = DebuggerMessage::AsyncTaskCreated {
+        function_name: function_name.to_string(),
+        task_id,
+        parent_id,
+    };
+
+    send_message(message)
+}
+
+/// Notify the debugger that an async task's state has changed
+pub fn notify_async_task_state_changed(
+    task_id: u64,
+    old_state: &str,
+    new_state: &str,
+    awaiting: Option<&str>,
+) -> DbugResult<()> {
+    let message = DebuggerMessage::AsyncTaskStateChanged {
+        task_id,
+        old_state: old_state.to_string(),
+        new_state: new_state.to_string(),
+        awaiting: awaiting.map(str::to_string),
+    };
+
+    send_message(message)
+}
+
+/// Notify the debugger that an async function has been entered
+pub fn notify_async_function_entered(function_name: &str, task_id: u64) -> DbugResult<()> {
+    let message = DebuggerMessage::AsyncFunctionEntered {
+        function_name: function_name.to_string(),
+        task_id,
+    };
+
+    send_message(message)
+}
+
+/// Notify the debugger that an async function has been exited
+pub fn notify_async_function_exited(function_name: &str, task_id: u64) -> DbugResult<()> {
+    let message = DebuggerMessage::AsyncFunctionExited {
+        function_name: function_name.to_string(),
+        task_id,
+    };
+
+    send_message(message)
+}
+
+/// Process an async debug point (breakpoint in async code)
+pub fn process_async_debug_point(
+    file: &str,
+    line: u32,
+    column: u32,
+    function: &str,
+    task_id: u64,
+) -> DbugResult<()> {
+    let message = DebuggerMessage::AsyncBreakPoint {
+        file: file.to_string(),
+        line,
+        column,
+        task_id,
+        function: function.to_string(),
+    };
+
+    send_message(message)?;
+
+    // The task stays stopped, like a synchronous breakpoint
+    wait_for_resume()
+}
+
+/// Initialize the communication channel for a debugging session
+///
+/// Called by the debugger before it spawns the debuggee. The returned
+/// rendezvous must be passed to the child with
+/// [`SessionRendezvous::apply_to_command`] so both sides share the channel.
+pub fn init_debugging_session(
+    transport: TransportKind,
+    buffer_full: BufferFullPolicy,
+) -> DbugResult<SessionRendezvous> {
+    let mut channel = COMMUNICATION_CHANNEL.lock().map_err(|_| {
+        DbugError::CommunicationError("Failed to lock communication channel".to_string())
+    })?;
+
+    let rendezvous = SessionRendezvous::create(transport, buffer_full)?;
+    *channel = CommunicationChannel::create(&rendezvous)?;
+
+    println!(
+        "[DBUG] Communication channel initialized at {} ({} transport)",
+        rendezvous.dir.display(),
+        rendezvous.transport
+    );
+    Ok(rendezvous)
+}
+
+/// Connect to a debuggee listening for a debugger over TCP
+///
+/// Called by `dbug attach` in place of [`init_debugging_session`].
+pub fn connect_to_debuggee(address: &str, token: &str) -> DbugResult<()> {
+    let mut channel = COMMUNICATION_CHANNEL.lock().map_err(|_| {
+        DbugError::CommunicationError("Failed to lock communication channel".to_string())
+    })?;
+
+    *channel = CommunicationChannel::connect(address, token)?;
+
+    println!("[DBUG] Connected to debuggee at {}", address);
+    Ok(())
+}
+
+/// Attach the current process to the debugger session named in its environment
+///
+/// Returns whether a debugger session is connected.
+pub fn attach_to_debugger() -> DbugResult<bool> {
+    let mut channel = COMMUNICATION_CHANNEL.lock().map_err(|_| {
+        DbugError::CommunicationError("Failed to lock communication channel".to_string())
+    })?;
+
+    if !channel.is_active() {
+        return Ok(false);
+    }
+
+    // A debugger that does not accept us must not receive any further messages
+    if let Err(e) = channel.handshake() {
+        let _ = channel.close();
+        return Err(e);
+    }
+
+    DEBUGGER_ATTACHED.store(true, Ordering::Relaxed);
+    Ok(true)
+}
+
+/// Clean up the communication channel after a debugging session
+pub fn cleanup_debugging_session() -> DbugResult<()> {
+    // Clean up the communication channel
+    // This would be called at the end of a debugging session
+    let mut channel = COMMUNICATION_CHANNEL.lock().map_err(|_| {
+        DbugError::CommunicationError("Failed to lock communication channel".to_string())
+    })?;
+
+    channel.close()?;
+    println!("[DBUG] Communication channel cleaned up");
+    Ok(())
+}
+
+/// Start reading events from the debugged process in the background
+///
+/// The pump keeps reading until the debuggee disconnects or the returned
+/// [`EventPump`] is dropped.
+pub fn start_event_pump() -> EventPump {
+    EventPump::start(Arc::clone(&COMMUNICATION_CHANNEL))
+}
+
+/// Handle incoming messages from the debugger
+#[allow(dead_code)]
+fn handle_message(message: DebuggerMessage) -> DbugResult<()> {
+    match message {
+        DebuggerMessage::Hello(hello) => {
+            // The handshake itself is answered by the channel
+            eprintln!(
+                "[DBUG] Debuggee connected: {} (pid {}, dbug {}, protocol {})",
+                hello.executable, hello.pid, hello.crate_version, hello.protocol_version
+            );
+            Ok(())
+        }
+
+        DebuggerMessage::BreakpointHit {
+            file,
+            line,
+            column,
+            function,
+            label,
+            call_stack: _,
+        } => {
+            // Process the breakpoint hit
+            match label {
+                Some(label) => eprintln!(
+                    "[DBUG] Breakpoint '{}' hit: {}:{}:{} in {}",
+                    label, file, line, column, function
+                ),
+                None => eprintln!(
+                    "[DBUG] Breakpoint hit: {}:{}:{} in {}",
+                    file, line, column, function
+                ),
+            }
+            Ok(())
+        }
+
+        DebuggerMessage::Paused { location } => {
+            // Process a requested pause
+            eprintln!("[DBUG] Paused while {}", location);
+            Ok(())
+        }
+
+        DebuggerMessage::Log {
+            file,
+            line,
+            message,
+        } => {
+            eprintln!("[DBUG] Log at {}:{}: {}", file, line, message);
+            Ok(())
+        }
+
+        DebuggerMessage::FunctionEntered {
+            function,
+            file,
+            line,
+        } => {
+            // Process function entry
+            eprintln!("[DBUG] Function entered: {} at {}:{}", function, file, line);
+            Ok(())
+        }
+
+        DebuggerMessage::FunctionExited {
+            function,
+            return_value,
+        } => {
+            // Process function exit
+            match return_value {
+                Some(value) => eprintln!("[DBUG] Function exited: {} -> {}", function, value),
+                None => eprintln!("[DBUG] Function exited: {}", function),
+            }
+            Ok(())
+        }
+
+        DebuggerMessage::VariableChanged {
+            name,
+            type_name,
+            value,
+            ..
+        } => {
+            // Process variable change
+            eprintln!(
+                "[DBUG] Variable changed: {} ({}) = {}",
+                name, type_name, value
+            );
+            Ok(())
+        }
+
+        DebuggerMessage::BatchedMessages(events) => {
+            // Process each message in the batch
+            for event in events {
+                handle_message(event.message)?;
+            }
+            Ok(())
+        }
+
+        DebuggerMessage::ExpressionResult { expression, result } => {
+            // Process expression result
+            eprintln!("[DBUG] Expression result: {} = {}", expression, result);
+            Ok(())
+        }
+
+        // Handle async-related messages
+        DebuggerMessage::AsyncTaskCreated {
+            function_name: _,
+            task_id: _,
+            parent_id: _,
+        } => {
+            // Already registered in the async_support module
+            Ok(())
+        }
+
+        DebuggerMessage::AsyncTaskStateChanged {
+            task_id: _,
+            old_state: _,
+            new_state: _,
+            awaiting: _,
+        } => {
+            // Already processed in the async_support module
+            Ok(())
+        }
+
+        DebuggerMessage::AsyncFunctionEntered {
+            function_name: _,
+            task_id: _,
+        } => {
+            // Already registered in the async_support module
+            Ok(())
+        }
+
+        DebuggerMessage::AsyncFunctionExited {
+            function_name: _,
+            task_id: _,
+        } => {
+            // Already processed in the async_support module
+            Ok(())
+        }
+
+        DebuggerMessage::AsyncBreakPoint {
+            file,
+            line,
+            column,
+            task_id,
+            function,
+        } => {
+            // Process the async breakpoint
+            crate::runtime::flow_control::handle_async_breakpoint(
+                &file, line, column, task_id, &function,
+            )?;
+
+            Ok(())
+        }
+    }
+}
+
+/// Replace the breakpoint table and push it to the debugged program
+pub fn set_breakpoints(breakpoints: Vec<BreakpointSpec>) -> DbugResult<()> {
+    let mut channel = COMMUNICATION_CHANNEL.lock().map_err(|_| {
+        DbugError::CommunicationError("Failed to lock communication channel".to_string())
+    })?;
+
+    channel.set_breakpoints(breakpoints)
+}
+
+/// Enable or disable the debug point at a location, returning whether it now stops
+///
+/// Locations without a breakpoint stop by default, so the first toggle disables them.
+pub fn toggle_breakpoint(file: &str, line: u32) -> DbugResult<bool> {
+    let mut channel = COMMUNICATION_CHANNEL.lock().map_err(|_| {
+        DbugError::CommunicationError("Failed to lock communication channel".to_string())
+    })?;
+
+    let mut breakpoints = channel.breakpoints().to_vec();
+    let enabled = match breakpoints
+        .iter_mut()
+        .find(|bp| bp.file == file && bp.line == line)
+    {
+        Some(breakpoint) => {
+            breakpoint.enabled = !breakpoint.enabled;
+            breakpoint.enabled
+        }
+        None => {
+            breakpoints.push(BreakpointSpec::new(file, line, false));
+            false
+        }
+    };
+
+    channel.set_breakpoints(breakpoints)?;
+    Ok(enabled)
+}
+
+/// Change the breakpoint at a location, returning it as pushed to the program
+///
+/// Locations without a breakpoint get an enabled one first.
+pub fn update_breakpoint(
+    file: &str,
+    line: u32,
+    edit: impl FnOnce(&mut BreakpointSpec),
+) -> DbugResult<BreakpointSpec> {
+    let mut channel = COMMUNICATION_CHANNEL.lock().map_err(|_| {
+        DbugError::CommunicationError("Failed to lock communication channel".to_string())
+    })?;
+
+    let mut breakpoints = channel.breakpoints().to_vec();
+    let index = match breakpoints
+        .iter()
+        .position(|bp| bp.file == file && bp.line == line)
+    {
+        Some(index) => index,
+        None => {
+            breakpoints.push(BreakpointSpec::new(file, line, true));
+            breakpoints.len() - 1
+        }
+    };
+    edit(&mut breakpoints[index]);
+
+    let breakpoint = breakpoints[index].clone();
+    channel.set_breakpoints(breakpoints)?;
+    Ok(breakpoint)
+}
+
+/// Ask the debugged program to stop at its next instrumentation point
+pub fn request_pause() -> DbugResult<()> {
+    send_request(DebuggerResponse::Pause, Capability::Pause, "pausing")
+}
+
+/// Ask the debugged program to carry on without the debugger
+pub fn request_detach(clear_breakpoints: bool) -> DbugResult<()> {
+    send_request(
+        DebuggerResponse::Detach { clear_breakpoints },
+        Capability::Detach,
+        "detaching",
+    )
+}
+
+/// Ask the debugged program to terminate
+pub fn request_kill() -> DbugResult<()> {
+    send_request(DebuggerResponse::Kill, Capability::Detach, "being killed")
+}
+
+/// Send a request the debuggee only understands if it negotiated `capability`
+fn send_request(request: DebuggerResponse, capability: Capability, what: &str) -> DbugResult<()> {
+    let mut channel = COMMUNICATION_CHANNEL.lock().map_err(|_| {
+        DbugError::CommunicationError("Failed to lock communication channel".to_string())
+    })?;
+
+    if !channel.has_capability(capability) {
+        return Err(DbugError::CommunicationError(format!(
+            "The program does not support {}",
+            what
+        )));
+    }
+
+    channel.send_response(&request)
+}
+
+/// Send a response to the instrumented code
+pub fn send_response(response: DebuggerResponse) -> DbugResult<()> {
+    let mut channel = COMMUNICATION_CHANNEL.lock().map_err(|_| {
+        DbugError::CommunicationError("Failed to lock communication channel".to_string())
+    })?;
+
+    channel.send_response(&response)
+}
@@ -0,0 +1,144 @@
+// Protocol versioning and handshake
+//
+// The debuggee opens every session with a `Hello` describing itself and the
+// protocol version it speaks. The debugger answers with a `Welcome` carrying
+// the negotiated version and capabilities, or refuses the connection.
+
+use crate::errors::{DbugError, DbugResult};
+use serde::{Deserialize, Serialize};
+
+/// Version of the wire protocol spoken by this build of dbug
+///
+/// Bump this whenever `DebuggerMessage` or `DebuggerResponse` change in a
+/// way an older peer would misparse.
+///
+/// Version 2 wraps every message in a `DebuggerEvent`. Version 3 adds the
+/// `Log` message and logpoints in breakpoint specs.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Oldest protocol version this build can still talk to
+pub const MIN_PROTOCOL_VERSION: u32 = 3;
+
+/// An optional feature one side of the channel supports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Async task tracking and async breakpoints
+    AsyncTasks,
+    /// Step over, into and out of functions
+    Stepping,
+    /// Evaluating expressions at a breakpoint
+    Evaluate,
+    /// Changing the value of a variable at a breakpoint
+    SetVariable,
+    /// Several messages batched into a single frame
+    BatchedMessages,
+    /// Pausing at the next instrumentation point on request
+    Pause,
+    /// Detaching from or killing the program on request
+    Detach,
+    /// Breakpoint tables pushed to the debuggee and decided there
+    BreakpointSync,
+    /// A capability added by a newer version of dbug
+    #[serde(other)]
+    Unknown,
+}
+
+/// The first message a debuggee sends after attaching
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    /// Protocol version spoken by the debuggee
+    pub protocol_version: u32,
+    /// Process id of the debuggee
+    pub pid: u32,
+    /// Name of the debuggee's executable
+    pub executable: String,
+    /// Version of the dbug crate the debuggee was built against
+    pub crate_version: String,
+    /// Features the debuggee supports
+    pub capabilities: Vec<Capability>,
+}
+
+impl Hello {
+    /// Describe the current process
+    pub fn current() -> Self {
+        let executable = std::env::current_exe()
+            .ok()
+            .and_then(|path| path.file_name().map(|n| n.to_string_lossy().to_string()))
+            .or_else(|| std::env::args().next())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            pid: std::process::id(),
+            executable,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities: CAPABILITIES.to_vec(),
+        }
+    }
+}
+
+/// The debugger's answer to an accepted `Hello`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Welcome {
+    /// Protocol version both sides will use
+    pub protocol_version: u32,
+    /// Version of the dbug crate the debugger was built from
+    pub crate_version: String,
+    /// Features supported by both sides
+    pub capabilities: Vec<Capability>,
+}
+
+impl Welcome {
+    /// Accept a debuggee's `Hello`, or explain why it is incompatible
+    pub fn negotiate(hello: &Hello) -> DbugResult<Self> {
+        let protocol_version = negotiate_version(hello.protocol_version)?;
+        let capabilities = hello
+            .capabilities
+            .iter()
+            .copied()
+            .filter(|capability| CAPABILITIES.contains(capability))
+            .collect();
+
+        Ok(Self {
+            protocol_version,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities,
+        })
+    }
+}
+
+/// Pick the protocol version to use with a peer speaking `peer_version`
+pub fn negotiate_version(peer_version: u32) -> DbugResult<u32> {
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&peer_version) {
+        return Err(DbugError::IncompatibleProtocol {
+            debugger: PROTOCOL_VERSION,
+            debuggee: peer_version,
+        });
+    }
+
+    Ok(peer_version)
+}
+
+/// Features this build supports, as the debugger and as the debuggee runtime
+pub const CAPABILITIES: &[Capability] = &[
+    Capability::AsyncTasks,
+    Capability::Stepping,
+    Capability::Evaluate,
+    Capability::BatchedMessages,
+    Capability::Pause,
+    Capability::Detach,
+    Capability::BreakpointSync,
+];
+
+/// Read the protocol version out of a `Hello` frame without parsing the rest
+///
+/// The rest of the `Hello` may have changed in another protocol version, so
+/// the version is checked before the frame is fully deserialized. Version 1
+/// sent the `Hello` on its own rather than inside an event.
+pub(crate) fn peek_hello_version(frame: &[u8]) -> Option<u32> {
+    let value: serde_json::Value = serde_json::from_slice(frame).ok()?;
+    let message = value.get("message").unwrap_or(&value);
+    let version = message.get("Hello")?.get("protocol_version")?.as_u64()?;
+    u32::try_from(version).ok()
+}
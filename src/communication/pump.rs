@@ -0,0 +1,216 @@
+// Debugger-side event pump
+//
+// A background thread reads frames from the debuggee, decodes them and hands
+// them to the debugger through a bounded queue. It waits for frames on its
+// own half of the transport and only takes the channel lock to decode one
+// that has arrived, so responses can still be sent while the pump is
+// running. Problems on the channel are reported as events too; one the
+// transport can't recover from ends the session.
+
+use super::{CommunicationChannel, DebuggerEvent, DebuggerMessage, FrameReceiver, Hello};
+use crate::errors::{DbugError, DbugResult};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// Number of events buffered before the pump stops reading from the channel
+const EVENT_QUEUE_CAPACITY: usize = 1024;
+// How often the reader checks for a session to start, and for room in a full queue
+const POLL_INTERVAL_MS: u64 = 10;
+// Longest the reader waits for a frame before checking whether it should stop
+const RECV_WAIT_MS: u64 = 100;
+
+/// Something that happened on the debugger's side of the channel
+#[derive(Debug)]
+pub enum ChannelEvent {
+    /// The debuggee completed the handshake
+    Connected(Hello),
+    /// A message from the debuggee; batches arrive as separate events
+    Message(DebuggerEvent),
+    /// A frame from the debuggee could not be decoded and was skipped
+    DecodeError(String),
+    /// The channel failed, for example because the handshake was refused
+    Error(DbugError),
+    /// The debuggee went away; no further events will follow
+    Disconnected,
+}
+
+/// Reads events from a communication channel on a background thread
+pub struct EventPump {
+    /// Decoded events, in the order they arrived
+    receiver: Receiver<ChannelEvent>,
+    /// Tells the reader thread to stop
+    stop: Arc<AtomicBool>,
+    /// The reader thread
+    handle: Option<JoinHandle<()>>,
+}
+
+impl EventPump {
+    /// Start reading events from `channel` (debugger side)
+    pub fn start(channel: Arc<Mutex<CommunicationChannel>>) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(EVENT_QUEUE_CAPACITY);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_stop = Arc::clone(&stop);
+        let handle = std::thread::Builder::new()
+            .name("dbug-event-pump".to_string())
+            .spawn(move || pump_events(channel, sender, thread_stop))
+            .ok();
+
+        Self {
+            receiver,
+            stop,
+            handle,
+        }
+    }
+
+    /// Take the next event if one is ready
+    pub fn try_next(&self) -> Option<ChannelEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Wait at most `timeout` for the next event
+    pub fn next_timeout(&self, timeout: Duration) -> Option<ChannelEvent> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+
+    /// Stop the reader thread and wait for it to finish
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        // Make room in case the reader is waiting on a full queue
+        while self.receiver.try_recv().is_ok() {}
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for EventPump {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Body of the reader thread
+fn pump_events(
+    channel: Arc<Mutex<CommunicationChannel>>,
+    sender: SyncSender<ChannelEvent>,
+    stop: Arc<AtomicBool>,
+) {
+    let Some(mut receiver) = wait_for_session(&channel, &sender, &stop) else {
+        return;
+    };
+    let wait = Duration::from_millis(RECV_WAIT_MS);
+
+    while !stop.load(Ordering::Relaxed) {
+        // Wait without the lock; it is only needed to decode what arrived
+        let result = match receiver.recv(wait) {
+            Ok(None) => continue,
+            Ok(Some(frame)) => lock(&channel).and_then(|mut channel| channel.decode_event(&frame)),
+            Err(DbugError::Disconnected) => Err(DbugError::Disconnected),
+            Err(e) => {
+                // A broken stream or ring fails the same way on every read, so
+                // the session ends here instead of reporting it over and over
+                deliver(&sender, &stop, ChannelEvent::Error(e));
+                if let Ok(mut channel) = lock(&channel) {
+                    let _ = channel.close();
+                }
+                Err(DbugError::Disconnected)
+            }
+        };
+
+        let (events, finished) = match result {
+            Ok(DebuggerEvent {
+                message: DebuggerMessage::Hello(hello),
+                ..
+            }) => (vec![ChannelEvent::Connected(hello)], false),
+            Ok(event) => (
+                event
+                    .flatten()
+                    .into_iter()
+                    .map(ChannelEvent::Message)
+                    .collect(),
+                false,
+            ),
+            Err(DbugError::Disconnected) => (vec![ChannelEvent::Disconnected], true),
+            Err(DbugError::JsonParse(e)) => (vec![ChannelEvent::DecodeError(e.to_string())], false),
+            Err(e) => (vec![ChannelEvent::Error(e)], false),
+        };
+
+        for event in events {
+            if !deliver(&sender, &stop, event) {
+                return;
+            }
+        }
+
+        if finished {
+            return;
+        }
+    }
+}
+
+/// Wait for the channel to join a session and take its receiving half
+///
+/// Returns `None` once the pump should stop, after reporting why.
+fn wait_for_session(
+    channel: &Mutex<CommunicationChannel>,
+    sender: &SyncSender<ChannelEvent>,
+    stop: &AtomicBool,
+) -> Option<Box<dyn FrameReceiver>> {
+    while !stop.load(Ordering::Relaxed) {
+        let split = lock(channel).and_then(|mut channel| {
+            // No session yet
+            if !channel.is_active() {
+                return Ok(None);
+            }
+            channel.split_receiver().map(Some)
+        });
+
+        match split {
+            Ok(Some(receiver)) => return Some(receiver),
+            Ok(None) => std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS)),
+            Err(e) => {
+                let event = match e {
+                    DbugError::Disconnected => ChannelEvent::Disconnected,
+                    e => ChannelEvent::Error(e),
+                };
+                deliver(sender, stop, event);
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// Take the channel lock, reporting a poisoned lock as an error
+fn lock(
+    channel: &Mutex<CommunicationChannel>,
+) -> DbugResult<std::sync::MutexGuard<'_, CommunicationChannel>> {
+    channel.lock().map_err(|_| {
+        DbugError::CommunicationError("Communication channel lock poisoned".to_string())
+    })
+}
+
+/// Queue an event, waiting while the queue is full; returns `false` once the pump should stop
+fn deliver(sender: &SyncSender<ChannelEvent>, stop: &AtomicBool, mut event: ChannelEvent) -> bool {
+    loop {
+        match sender.try_send(event) {
+            Ok(()) => return true,
+            Err(TrySendError::Disconnected(_)) => return false,
+            Err(TrySendError::Full(returned)) => {
+                if stop.load(Ordering::Relaxed) {
+                    return false;
+                }
+                event = returned;
+                std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+            }
+        }
+    }
+}
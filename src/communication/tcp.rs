@@ -0,0 +1,247 @@
+// TCP transport
+//
+// Used to debug a process on another host or inside a container. Unlike the
+// local transports, the debuggee listens and the debugger connects to it. The
+// debugger has to present the session token as its first frame before any
+// message or response is exchanged, so a stray connection cannot drive the
+// process.
+
+use super::framing::{
+    is_disconnect, read_frame, read_frame_within, write_frame, MAX_FRAME_SIZE, MIN_RECEIVER_WAIT,
+};
+use super::transport::{receiver_split, FrameReceiver, Transport};
+use crate::errors::{DbugError, DbugResult};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+// How long either side waits for the other during authentication
+const AUTH_TIMEOUT_MS: u64 = 5000;
+// Largest token frame accepted from a connection that is not authenticated yet
+const MAX_TOKEN_SIZE: usize = 1024;
+
+// Replies to the token frame
+const AUTH_ACCEPTED: &[u8] = b"ok";
+const AUTH_REJECTED: &[u8] = b"denied";
+
+/// Transport over an authenticated TCP connection
+pub struct TcpTransport {
+    /// The connected stream
+    stream: Option<TcpStream>,
+    /// Whether the other side has disconnected
+    disconnected: bool,
+    /// Whether the receiving half was split off
+    split: bool,
+}
+
+/// The receiving half of a [`TcpTransport`]
+pub struct TcpReceiver {
+    stream: Option<TcpStream>,
+}
+
+impl TcpTransport {
+    /// Listen on `address` until a debugger presents `token` (debuggee side)
+    pub fn listen(address: &str, token: &str) -> DbugResult<Self> {
+        let listener = TcpListener::bind(address).map_err(|e| {
+            DbugError::CommunicationError(format!("Failed to listen on {}: {}", address, e))
+        })?;
+
+        Self::accept(&listener, token)
+    }
+
+    /// Accept connections until one presents `token` (debuggee side)
+    ///
+    /// Connections with a wrong or missing token are refused and closed.
+    pub fn accept(listener: &TcpListener, token: &str) -> DbugResult<Self> {
+        loop {
+            let (mut stream, peer) = listener.accept().map_err(|e| {
+                DbugError::CommunicationError(format!("Failed to accept connection: {}", e))
+            })?;
+
+            match authenticate(&mut stream, token) {
+                Ok(true) => {
+                    let _ = stream.set_nodelay(true);
+                    return Ok(Self::new(stream));
+                }
+                Ok(false) => {
+                    eprintln!(
+                        "[DBUG] Refused connection from {}: wrong session token",
+                        peer
+                    )
+                }
+                Err(e) => eprintln!("[DBUG] Refused connection from {}: {}", peer, e),
+            }
+        }
+    }
+
+    /// Connect to a listening debuggee and present `token` (debugger side)
+    pub fn connect(address: &str, token: &str) -> DbugResult<Self> {
+        let mut stream = TcpStream::connect(address).map_err(|e| {
+            DbugError::CommunicationError(format!("Failed to connect to {}: {}", address, e))
+        })?;
+        let _ = stream.set_nodelay(true);
+
+        let reply = write_frame(&mut stream, token.as_bytes())
+            .and_then(|_| {
+                read_frame(
+                    &mut stream,
+                    Duration::from_millis(AUTH_TIMEOUT_MS),
+                    MAX_TOKEN_SIZE,
+                )
+            })
+            .map_err(|e| {
+                DbugError::CommunicationError(format!(
+                    "Failed to authenticate with {}: {}",
+                    address, e
+                ))
+            })?;
+
+        match reply.as_deref() {
+            Some(AUTH_ACCEPTED) => Ok(Self::new(stream)),
+            Some(_) => Err(DbugError::CommunicationError(format!(
+                "{} rejected the session token",
+                address
+            ))),
+            None => Err(DbugError::CommunicationError(format!(
+                "Timed out authenticating with {}",
+                address
+            ))),
+        }
+    }
+
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream: Some(stream),
+            disconnected: false,
+            split: false,
+        }
+    }
+
+    /// Mark the connection as lost and drop the stream
+    fn mark_disconnected(&mut self) -> DbugError {
+        self.disconnected = true;
+        self.stream = None;
+        DbugError::Disconnected
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, frame: &[u8]) -> DbugResult<()> {
+        let stream = match self.stream.as_mut() {
+            Some(stream) if !self.disconnected => stream,
+            _ => return Err(DbugError::Disconnected),
+        };
+
+        match write_frame(stream, frame) {
+            Ok(()) => Ok(()),
+            Err(e) if is_disconnect(e.kind()) => Err(self.mark_disconnected()),
+            Err(e) => Err(DbugError::CommunicationError(format!(
+                "Failed to write to connection: {}",
+                e
+            ))),
+        }
+    }
+
+    fn recv(&mut self, timeout: Duration) -> DbugResult<Option<Vec<u8>>> {
+        if self.split {
+            return Err(receiver_split());
+        }
+        let stream = match self.stream.as_mut() {
+            Some(stream) if !self.disconnected => stream,
+            _ => return Err(DbugError::Disconnected),
+        };
+
+        match recv_frame(stream, timeout) {
+            Err(DbugError::Disconnected) => Err(self.mark_disconnected()),
+            result => result,
+        }
+    }
+
+    fn split_receiver(&mut self) -> DbugResult<Box<dyn FrameReceiver>> {
+        if self.split {
+            return Err(receiver_split());
+        }
+        let stream = match self.stream.as_ref() {
+            Some(stream) if !self.disconnected => stream.try_clone().map_err(|e| {
+                DbugError::CommunicationError(format!("Failed to share connection: {}", e))
+            })?,
+            _ => return Err(DbugError::Disconnected),
+        };
+        self.split = true;
+
+        Ok(Box::new(TcpReceiver {
+            stream: Some(stream),
+        }))
+    }
+
+    fn is_connected(&self) -> bool {
+        !self.disconnected
+    }
+
+    fn close(&mut self) -> DbugResult<()> {
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+        Ok(())
+    }
+}
+
+impl FrameReceiver for TcpReceiver {
+    fn recv(&mut self, timeout: Duration) -> DbugResult<Option<Vec<u8>>> {
+        let stream = self.stream.as_mut().ok_or(DbugError::Disconnected)?;
+
+        let result = recv_frame(stream, timeout.max(MIN_RECEIVER_WAIT));
+        if matches!(result, Err(DbugError::Disconnected)) {
+            self.stream = None;
+        }
+        result
+    }
+}
+
+/// Read a frame from the connection, reporting end-of-stream as a disconnect
+fn recv_frame(stream: &mut TcpStream, timeout: Duration) -> DbugResult<Option<Vec<u8>>> {
+    match read_frame(stream, timeout, MAX_FRAME_SIZE) {
+        Ok(frame) => Ok(frame),
+        Err(e) if is_disconnect(e.kind()) => Err(DbugError::Disconnected),
+        Err(e) => Err(DbugError::CommunicationError(format!(
+            "Failed to read from connection: {}",
+            e
+        ))),
+    }
+}
+
+/// Check the token presented by a new connection and tell it the outcome
+///
+/// The whole token has to arrive within the timeout, so a connection that
+/// stalls cannot keep the real debugger from being accepted.
+fn authenticate(stream: &mut TcpStream, token: &str) -> std::io::Result<bool> {
+    let presented = read_frame_within(
+        stream,
+        Duration::from_millis(AUTH_TIMEOUT_MS),
+        MAX_TOKEN_SIZE,
+    )?;
+
+    let accepted = presented
+        .map(|presented| constant_time_eq(&presented, token.as_bytes()))
+        .unwrap_or(false);
+
+    let reply = if accepted {
+        AUTH_ACCEPTED
+    } else {
+        AUTH_REJECTED
+    };
+    write_frame(stream, reply)?;
+
+    if !accepted {
+        let _ = stream.shutdown(std::net::Shutdown::Both);
+    }
+    Ok(accepted)
+}
+
+/// Compare two byte strings without leaking where they differ through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,162 @@
+// Session traces
+//
+// A trace records everything a debuggee sent during a session, so the
+// session can be replayed later without the program. Traces are JSON lines:
+// a header record first, then one record per line in the order the debugger
+// received them. Events keep the thread and timestamp they were sent with.
+
+use super::{DebuggerEvent, Hello, PROTOCOL_VERSION};
+use crate::errors::{DbugError, DbugResult};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Version of the trace file format
+pub const TRACE_FORMAT_VERSION: u32 = 1;
+
+/// A single line of a trace file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum TraceRecord {
+    /// Always the first line of the file
+    Header {
+        /// Version of the trace file format
+        format_version: u32,
+        /// Protocol version of the recording debugger
+        protocol_version: u32,
+    },
+    /// The debuggee completed the handshake
+    Connected { hello: Hello },
+    /// A message from the debuggee
+    Event { event: DebuggerEvent },
+}
+
+/// Writes the events of a live session to a trace file
+pub struct TraceRecorder {
+    writer: BufWriter<File>,
+}
+
+impl TraceRecorder {
+    /// Create the trace file at `path`, replacing any existing file
+    pub fn create(path: &Path) -> DbugResult<Self> {
+        let file = File::create(path).map_err(|e| {
+            DbugError::CommunicationError(format!(
+                "Failed to create trace file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        let mut recorder = Self {
+            writer: BufWriter::new(file),
+        };
+        recorder.write(&TraceRecord::Header {
+            format_version: TRACE_FORMAT_VERSION,
+            protocol_version: PROTOCOL_VERSION,
+        })?;
+
+        Ok(recorder)
+    }
+
+    /// Record the debuggee's handshake
+    pub fn record_connected(&mut self, hello: &Hello) -> DbugResult<()> {
+        self.write(&TraceRecord::Connected {
+            hello: hello.clone(),
+        })?;
+        self.flush()
+    }
+
+    /// Record a message from the debuggee
+    pub fn record_event(&mut self, event: &DebuggerEvent) -> DbugResult<()> {
+        self.write(&TraceRecord::Event {
+            event: event.clone(),
+        })
+    }
+
+    /// Make sure everything recorded so far is on disk
+    pub fn flush(&mut self) -> DbugResult<()> {
+        self.writer.flush().map_err(DbugError::from)
+    }
+
+    fn write(&mut self, record: &TraceRecord) -> DbugResult<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl Drop for TraceRecorder {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// A recorded session loaded from a trace file
+#[derive(Debug, Default)]
+pub struct Trace {
+    /// The debuggee's handshake, if it connected
+    pub hello: Option<Hello>,
+    /// Every message the debuggee sent, in the order it was received
+    pub events: Vec<DebuggerEvent>,
+}
+
+impl Trace {
+    /// Load a trace file
+    ///
+    /// A trace cut short by a crash is loaded up to its last complete line.
+    pub fn load(path: &Path) -> DbugResult<Self> {
+        let file = File::open(path).map_err(|e| {
+            DbugError::CommunicationError(format!(
+                "Failed to open trace file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let mut lines = BufReader::new(file).lines();
+
+        match lines.next().transpose()? {
+            Some(line) => match serde_json::from_str(&line) {
+                Ok(TraceRecord::Header { format_version, .. })
+                    if format_version <= TRACE_FORMAT_VERSION => {}
+                Ok(TraceRecord::Header { format_version, .. }) => {
+                    return Err(DbugError::CommunicationError(format!(
+                        "Trace file {} has format version {}, newer than this dbug supports",
+                        path.display(),
+                        format_version
+                    )))
+                }
+                _ => {
+                    return Err(DbugError::CommunicationError(format!(
+                        "{} is not a dbug trace file",
+                        path.display()
+                    )))
+                }
+            },
+            None => {
+                return Err(DbugError::CommunicationError(format!(
+                    "Trace file {} is empty",
+                    path.display()
+                )))
+            }
+        }
+
+        let mut trace = Trace::default();
+        for line in lines {
+            let record = match serde_json::from_str(&line?) {
+                Ok(record) => record,
+                // Only the last line can be incomplete
+                Err(e) if e.is_eof() => break,
+                Err(e) => return Err(e.into()),
+            };
+
+            match record {
+                TraceRecord::Connected { hello } => trace.hello = Some(hello),
+                TraceRecord::Event { event } => trace.events.push(event),
+                TraceRecord::Header { .. } => {}
+            }
+        }
+
+        Ok(trace)
+    }
+}
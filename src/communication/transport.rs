@@ -0,0 +1,143 @@
+// Transport abstraction for the communication channel
+//
+// A transport moves opaque frames between the debugger and the debuggee.
+// Serialization of messages and responses is handled by the channel on top.
+
+use crate::errors::DbugResult;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Which side of the channel a transport endpoint sits on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelRole {
+    /// The debugger process, which receives messages and sends responses
+    Debugger,
+    /// The instrumented program, which sends messages and receives responses
+    Debuggee,
+}
+
+/// The kind of transport used by a debugging session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransportKind {
+    /// Memory-mapped files in the session directory
+    #[default]
+    Mmap,
+    /// A Unix domain socket in the session directory
+    UnixSocket,
+    /// A TCP connection, for debugging a process on another host
+    Tcp,
+}
+
+impl fmt::Display for TransportKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mmap => write!(f, "mmap"),
+            Self::UnixSocket => write!(f, "unix"),
+            Self::Tcp => write!(f, "tcp"),
+        }
+    }
+}
+
+impl FromStr for TransportKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "mmap" => Ok(Self::Mmap),
+            "unix" | "uds" | "socket" => Ok(Self::UnixSocket),
+            "tcp" => Ok(Self::Tcp),
+            other => Err(format!(
+                "Unknown transport '{}' (expected 'mmap', 'unix' or 'tcp')",
+                other
+            )),
+        }
+    }
+}
+
+/// What a transport does when its outbound buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BufferFullPolicy {
+    /// Wait for the other side to make room, so no message is lost
+    #[default]
+    Block,
+    /// Discard the message and count it, so the program never stalls
+    Drop,
+}
+
+impl fmt::Display for BufferFullPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Block => write!(f, "block"),
+            Self::Drop => write!(f, "drop"),
+        }
+    }
+}
+
+impl FromStr for BufferFullPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "block" => Ok(Self::Block),
+            "drop" => Ok(Self::Drop),
+            other => Err(format!(
+                "Unknown buffer-full policy '{}' (expected 'block' or 'drop')",
+                other
+            )),
+        }
+    }
+}
+
+/// Moves frames between the debugger and the debuggee
+pub trait Transport: Send {
+    /// Send a single frame to the other side
+    fn send(&mut self, frame: &[u8]) -> DbugResult<()>;
+
+    /// Receive a single frame, waiting at most `timeout` for one to arrive
+    ///
+    /// Returns `Ok(None)` if nothing arrived in time, and
+    /// `Err(DbugError::Disconnected)` once the other side has gone away.
+    fn recv(&mut self, timeout: Duration) -> DbugResult<Option<Vec<u8>>>;
+
+    /// Split off the receiving half, so frames can be waited for on another thread
+    ///
+    /// The transport keeps sending; from now on frames only arrive through
+    /// the returned receiver.
+    fn split_receiver(&mut self) -> DbugResult<Box<dyn FrameReceiver>>;
+
+    /// Whether the other side is still known to be connected
+    fn is_connected(&self) -> bool;
+
+    /// Number of frames the other side dropped before they reached us
+    fn dropped_frames(&self) -> u64 {
+        0
+    }
+
+    /// Release the resources held by the transport
+    fn close(&mut self) -> DbugResult<()>;
+}
+
+/// The receiving half of a transport, split off with [`Transport::split_receiver`]
+pub trait FrameReceiver: Send {
+    /// Receive a single frame, waiting at most `timeout` for one to arrive
+    ///
+    /// Behaves like [`Transport::recv`].
+    fn recv(&mut self, timeout: Duration) -> DbugResult<Option<Vec<u8>>>;
+}
+
+/// Error returned when reading from a transport whose receiving half was split off
+pub(crate) fn receiver_split() -> crate::errors::DbugError {
+    crate::errors::DbugError::CommunicationError(
+        "Frames are read by the receiver split off this transport".to_string(),
+    )
+}
+
+/// Error returned when a transport is not available on this platform
+#[cfg(not(unix))]
+pub(crate) fn unsupported(kind: TransportKind) -> crate::errors::DbugError {
+    crate::errors::DbugError::CommunicationError(format!(
+        "The {} transport is not supported on this platform",
+        kind
+    ))
+}
@@ -0,0 +1,294 @@
+// Unix domain socket transport
+//
+// The debugger listens on a socket inside the session directory and the
+// debuggee connects to it. Frames are prefixed with their length, reads block
+// until data arrives, and end-of-stream is reported as a disconnect.
+// Framing is shared with the TCP transport.
+//
+// Unlike TCP there is no token exchange: the socket lives in the session
+// directory, which only its owner can open, and both sides refuse to use a
+// directory that other users can reach.
+
+use super::framing::{is_disconnect, read_frame, write_frame, MAX_FRAME_SIZE, MIN_RECEIVER_WAIT};
+use super::transport::{receiver_split, FrameReceiver, Transport};
+use super::SessionRendezvous;
+use crate::errors::{DbugError, DbugResult};
+use once_cell::sync::OnceCell;
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// How long to sleep between accept attempts while waiting for the debuggee
+const ACCEPT_POLL_INTERVAL_MS: u64 = 10;
+
+/// The socket connection, shared by the sending and receiving halves
+struct Connection {
+    /// Listener waiting for the debuggee (debugger side only)
+    listener: Mutex<Option<UnixListener>>,
+    /// The connected stream, once either half has accepted it
+    stream: OnceCell<UnixStream>,
+}
+
+impl Connection {
+    /// A handle on the connected stream, accepting it if it has arrived within `timeout`
+    fn accept(&self, timeout: Duration) -> DbugResult<Option<UnixStream>> {
+        let start_time = Instant::now();
+        loop {
+            if let Some(stream) = self.stream.get() {
+                return stream.try_clone().map(Some).map_err(|e| {
+                    DbugError::CommunicationError(format!("Failed to share socket: {}", e))
+                });
+            }
+
+            let accepted = {
+                let listener = self.listener.lock().map_err(|_| {
+                    DbugError::CommunicationError("Socket listener lock poisoned".to_string())
+                })?;
+                match listener.as_ref() {
+                    Some(listener) => listener.accept(),
+                    None => return Err(DbugError::Disconnected),
+                }
+            };
+
+            match accepted {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false).map_err(|e| {
+                        DbugError::CommunicationError(format!("Failed to configure socket: {}", e))
+                    })?;
+                    // If the other half got there first, its stream is used
+                    let _ = self.stream.set(stream);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    if start_time.elapsed() >= timeout {
+                        return Ok(None);
+                    }
+                    std::thread::sleep(Duration::from_millis(ACCEPT_POLL_INTERVAL_MS));
+                }
+                Err(e) => {
+                    return Err(DbugError::CommunicationError(format!(
+                        "Failed to accept connection: {}",
+                        e
+                    )))
+                }
+            }
+        }
+    }
+}
+
+/// Transport over a Unix domain socket
+pub struct UnixSocketTransport {
+    /// The connection, shared with a split-off receiver
+    connection: Arc<Connection>,
+    /// This half's handle on the stream, once connected
+    stream: Option<UnixStream>,
+    /// Whether the other side has disconnected
+    disconnected: bool,
+    /// Whether the receiving half was split off
+    split: bool,
+}
+
+/// The receiving half of a [`UnixSocketTransport`]
+pub struct UnixSocketReceiver {
+    connection: Arc<Connection>,
+    stream: Option<UnixStream>,
+    disconnected: bool,
+}
+
+impl UnixSocketTransport {
+    /// Listen for the debuggee on the session socket (debugger side)
+    pub fn listen(rendezvous: &SessionRendezvous) -> DbugResult<Self> {
+        check_private(&rendezvous.dir)?;
+        let path = rendezvous.socket_path();
+        let listener = UnixListener::bind(&path).map_err(|e| {
+            DbugError::CommunicationError(format!(
+                "Failed to bind socket {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        listener.set_nonblocking(true).map_err(|e| {
+            DbugError::CommunicationError(format!("Failed to configure socket: {}", e))
+        })?;
+
+        Ok(Self::new(Connection {
+            listener: Mutex::new(Some(listener)),
+            stream: OnceCell::new(),
+        }))
+    }
+
+    /// Connect to the debugger's session socket (debuggee side)
+    pub fn connect(rendezvous: &SessionRendezvous) -> DbugResult<Self> {
+        check_private(&rendezvous.dir)?;
+        let path = rendezvous.socket_path();
+        let stream = UnixStream::connect(&path).map_err(|e| {
+            DbugError::CommunicationError(format!(
+                "Failed to connect to socket {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        Ok(Self::new(Connection {
+            listener: Mutex::new(None),
+            stream: OnceCell::with_value(stream),
+        }))
+    }
+
+    fn new(connection: Connection) -> Self {
+        Self {
+            connection: Arc::new(connection),
+            stream: None,
+            disconnected: false,
+            split: false,
+        }
+    }
+
+    /// Accept the debuggee's connection if it has arrived, waiting at most `timeout`
+    fn accept(&mut self, timeout: Duration) -> DbugResult<bool> {
+        if self.stream.is_none() {
+            self.stream = self.connection.accept(timeout)?;
+        }
+        Ok(self.stream.is_some())
+    }
+
+    /// Mark the connection as lost and drop the stream
+    fn mark_disconnected(&mut self) -> DbugError {
+        self.disconnected = true;
+        self.stream = None;
+        DbugError::Disconnected
+    }
+}
+
+impl Transport for UnixSocketTransport {
+    fn send(&mut self, frame: &[u8]) -> DbugResult<()> {
+        if self.disconnected {
+            return Err(DbugError::Disconnected);
+        }
+
+        if !self.accept(Duration::ZERO)? {
+            return Err(DbugError::CommunicationError(
+                "The debuggee has not connected yet".to_string(),
+            ));
+        }
+
+        let stream = self.stream.as_mut().ok_or(DbugError::Disconnected)?;
+        match write_frame(stream, frame) {
+            Ok(()) => Ok(()),
+            Err(e) if is_disconnect(e.kind()) => Err(self.mark_disconnected()),
+            Err(e) => Err(DbugError::CommunicationError(format!(
+                "Failed to write to socket: {}",
+                e
+            ))),
+        }
+    }
+
+    fn recv(&mut self, timeout: Duration) -> DbugResult<Option<Vec<u8>>> {
+        if self.split {
+            return Err(receiver_split());
+        }
+        if self.disconnected {
+            return Err(DbugError::Disconnected);
+        }
+
+        let start_time = Instant::now();
+        if !self.accept(timeout)? {
+            return Ok(None);
+        }
+        let remaining = timeout.saturating_sub(start_time.elapsed());
+
+        let stream = self.stream.as_mut().ok_or(DbugError::Disconnected)?;
+        match recv_frame(stream, remaining) {
+            Err(DbugError::Disconnected) => Err(self.mark_disconnected()),
+            result => result,
+        }
+    }
+
+    fn split_receiver(&mut self) -> DbugResult<Box<dyn FrameReceiver>> {
+        if self.split {
+            return Err(receiver_split());
+        }
+        self.split = true;
+
+        Ok(Box::new(UnixSocketReceiver {
+            connection: Arc::clone(&self.connection),
+            stream: None,
+            disconnected: self.disconnected,
+        }))
+    }
+
+    fn is_connected(&self) -> bool {
+        !self.disconnected
+    }
+
+    fn close(&mut self) -> DbugResult<()> {
+        // Shutting the socket down also wakes a receiver waiting on it
+        self.stream = None;
+        if let Some(stream) = self.connection.stream.get() {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+        if let Ok(mut listener) = self.connection.listener.lock() {
+            *listener = None;
+        }
+        Ok(())
+    }
+}
+
+impl FrameReceiver for UnixSocketReceiver {
+    fn recv(&mut self, timeout: Duration) -> DbugResult<Option<Vec<u8>>> {
+        if self.disconnected {
+            return Err(DbugError::Disconnected);
+        }
+
+        let start_time = Instant::now();
+        if self.stream.is_none() {
+            self.stream = self.connection.accept(timeout)?;
+        }
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(None);
+        };
+        let remaining = timeout.saturating_sub(start_time.elapsed());
+
+        let result = recv_frame(stream, remaining.max(MIN_RECEIVER_WAIT));
+        if matches!(result, Err(DbugError::Disconnected)) {
+            self.disconnected = true;
+            self.stream = None;
+        }
+        result
+    }
+}
+
+/// Read a frame from the socket, reporting end-of-stream as a disconnect
+fn recv_frame(stream: &mut UnixStream, timeout: Duration) -> DbugResult<Option<Vec<u8>>> {
+    match read_frame(stream, timeout, MAX_FRAME_SIZE) {
+        Ok(frame) => Ok(frame),
+        Err(e) if is_disconnect(e.kind()) => Err(DbugError::Disconnected),
+        Err(e) => Err(DbugError::CommunicationError(format!(
+            "Failed to read from socket: {}",
+            e
+        ))),
+    }
+}
+
+/// Refuse a session directory that users other than its owner can open
+fn check_private(dir: &Path) -> DbugResult<()> {
+    let metadata = std::fs::metadata(dir).map_err(|e| {
+        DbugError::CommunicationError(format!(
+            "Failed to read session directory {}: {}",
+            dir.display(),
+            e
+        ))
+    })?;
+
+    if metadata.permissions().mode() & 0o077 != 0 {
+        return Err(DbugError::CommunicationError(format!(
+            "Session directory {} is accessible to other users",
+            dir.display()
+        )));
+    }
+
+    Ok(())
+}
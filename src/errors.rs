@@ -29,6 +29,14 @@ pub enum DbugError {
     #[error("Timeout waiting for response")]
     ResponseTimeout,
 
+    #[error("The other side of the debugging channel disconnected")]
+    Disconnected,
+
+    #[error(
+        "Incompatible protocol version: debugger speaks {debugger}, debuggee speaks {debuggee}"
+    )]
+    IncompatibleProtocol { debugger: u32, debuggee: u32 },
+
     #[error("Failed to parse source file: {0}")]
     SourceParseError(String),
 
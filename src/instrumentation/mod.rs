@@ -29,7 +29,6 @@ pub enum DebugPointType {
     /// A watch point that displays a value
     Watchpoint(String),
     /// A log point that prints a message
-    #[allow(dead_code)]
     LogPoint(String),
 }
 
@@ -53,7 +52,6 @@ impl DebugPoint {
     }
 
     /// Create a new logpoint with a message
-    #[allow(dead_code)]
     pub fn logpoint(file: &str, line: u32, message: &str) -> Self {
         Self {
             file: file.to_string(),
@@ -83,13 +81,23 @@ impl<'a> DebugPointVisitor<'a> {
     fn check_macro(&mut self, mac: &Macro) {
         let mac_str = mac.to_token_stream().to_string();
 
-        // Check for break_here! macro calls
-        if mac_str.contains("break_here !") {
+        // Check for break_here! and break_when! macro calls
+        if mac_str.contains("break_here !") || mac_str.contains("break_when !") {
             // Get a reasonable line number - this is a simplification
             self.debug_points
                 .push(DebugPoint::breakpoint(self.file_path, self.current_line));
         }
 
+        // Check for log_point! macros
+        if mac_str.contains("log_point !") {
+            let message = mac.tokens.to_string();
+            self.debug_points.push(DebugPoint::logpoint(
+                self.file_path,
+                self.current_line,
+                &message,
+            ));
+        }
+
         // Check for watch macros
         if mac_str.contains("watch !") {
             let expr = mac.tokens.to_string();
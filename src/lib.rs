@@ -23,24 +23,49 @@ pub mod _internal {
     use crate::errors::DbugResult;
     use crate::runtime::async_support::{
         generate_async_task_id as runtime_generate_task_id,
-        get_current_async_task_id as runtime_get_task_id, TaskId,
+        get_current_async_task_id as runtime_get_task_id, AsyncTaskState, TaskId,
     };
+    pub use crate::runtime::gate::hooks_enabled;
+    use crate::runtime::{DbugInspect, VariableValue};
+    use std::future::Future;
+    use std::pin::Pin;
     use std::sync::Once;
+    use std::task::{Context, Poll};
 
     static INIT: Once = Once::new();
 
+    /// Print a line to stderr when `DBUG_LOG=trace` is set
+    macro_rules! trace {
+        ($($arg:tt)*) => {
+            if crate::runtime::gate::trace_enabled() {
+                eprintln!("[DBUG] {}", format_args!($($arg)*));
+            }
+        };
+    }
+
     /// Initialize the debugging runtime
     pub fn init() {
         INIT.call_once(|| {
-            // Initialize the debugging runtime
-            // Any one-time initialization goes here
-            eprintln!("[DBUG] Initializing debug runtime");
+            // Attach to the channel the debugger created for this process
+            match crate::communication::attach_to_debugger() {
+                Ok(true) => trace!("Attached to debugger session"),
+                Ok(false) => trace!("Initializing debug runtime (no debugger attached)"),
+                Err(e) => eprintln!("[DBUG] Error attaching to debugger: {}", e),
+            }
         });
     }
 
     /// Called when entering a function that's marked for debugging
-    pub fn enter_function(function_name: &str) {
+    ///
+    /// Like every hook, this does nothing unless the program was started by
+    /// the debugger or `DBUG_LOG=trace` is set.
+    pub fn enter_function(function_name: &'static str) {
+        if !hooks_enabled() {
+            return;
+        }
         init();
+        crate::runtime::flow_control::enter_call();
+        crate::runtime::flow_control::push_function(function_name);
 
         // Get the current file and line number
         let _file = std::panic::Location::caller().file();
@@ -51,48 +76,129 @@ pub mod _internal {
             eprintln!("[DBUG] Error notifying function entry: {}", e);
         }
 
-        // Also log to console in development mode
-        eprintln!("[DBUG] Entering function: {}", function_name);
+        trace!("Entering function: {}", function_name);
+
+        poll_debugger(|| format!("entering {}", function_name));
     }
 
     /// Called when exiting a function that's marked for debugging
     pub fn exit_function(function_name: &str) {
+        exit_function_returning(function_name, None);
+    }
+
+    /// Called when exiting a function marked with `#[dbug(ret)]`
+    ///
+    /// `return_value` is the `Debug` rendering of what the function returned,
+    /// or `None` if it unwound or the value wasn't captured.
+    pub fn exit_function_returning(function_name: &str, return_value: Option<&str>) {
+        if !hooks_enabled() {
+            return;
+        }
+        crate::runtime::flow_control::exit_call();
+        crate::runtime::flow_control::pop_function();
+
         // Notify the debugger
-        if let Err(e) = crate::communication::notify_function_exited(function_name) {
+        if let Err(e) = crate::communication::notify_function_exited(function_name, return_value) {
             eprintln!("[DBUG] Error notifying function exit: {}", e);
         }
 
-        // Also log to console in development mode
-        eprintln!("[DBUG] Exiting function: {}", function_name);
+        match return_value {
+            Some(value) => trace!("Exiting function: {} -> {}", function_name, value),
+            None => trace!("Exiting function: {}", function_name),
+        }
+
+        poll_debugger(|| format!("exiting {}", function_name));
     }
 
     /// Called when a breakpoint is encountered
-    pub fn break_point(file: &str, line: u32, column: u32) {
-        init();
+    ///
+    /// `function` is the path of the enclosing `#[dbug]` function, or the
+    /// module path when the breakpoint is outside one.
+    pub fn break_point(file: &str, line: u32, column: u32, function: &str) {
+        break_point_with_label(file, line, column, function, None);
+    }
 
-        // Use a simpler approach - we'll use the file name to guess the function
-        // In a real implementation, this would use DWARF debug info to get the actual function name
-        let file_stem = std::path::Path::new(file)
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("unknown");
+    /// Called when a breakpoint given a label with `break_here!(label = "...")` is encountered
+    pub fn break_point_with_label(
+        file: &str,
+        line: u32,
+        column: u32,
+        function: &str,
+        label: Option<&str>,
+    ) {
+        if !hooks_enabled() {
+            return;
+        }
+        init();
 
-        let current_function = format!("function_in_{}", file_stem);
+        // Disabled and unmet breakpoints are decided here, without a round-trip
+        if !crate::runtime::flow_control::should_break_at(file, line) {
+            return;
+        }
 
         // Process the debug point
         if let Err(e) =
-            crate::communication::process_debug_point(file, line, column, &current_function)
+            crate::communication::process_debug_point(file, line, column, function, label)
         {
             eprintln!("[DBUG] Error processing debug point: {}", e);
         }
 
-        // Also log to console in development mode
-        eprintln!(
-            "[DBUG] Breakpoint at {}:{}:{} in {}",
-            file, line, column, current_function
+        trace!(
+            "Breakpoint{} at {}:{}:{} in {}",
+            label.map(|l| format!(" '{}'", l)).unwrap_or_default(),
+            file,
+            line,
+            column,
+            function
         );
     }
 
+    /// Called before each statement of a `#[dbug]` function
+    ///
+    /// Stops only where a step ends or at a line the debugger set a
+    /// breakpoint on, so it costs next to nothing otherwise.
+    pub fn checkpoint(file: &str, line: u32, column: u32, function: &str) {
+        if !hooks_enabled() {
+            return;
+        }
+        init();
+        if !crate::communication::is_attached() {
+            return;
+        }
+
+        poll_debugger(|| format!("at {}:{}", file, line));
+
+        if !crate::runtime::flow_control::should_stop_at_checkpoint(file, line) {
+            return;
+        }
+
+        if let Err(e) =
+            crate::communication::process_debug_point(file, line, column, function, None)
+        {
+            eprintln!("[DBUG] Error processing debug point: {}", e);
+        }
+    }
+
+    /// Called by `log_point!` to send a message without stopping
+    ///
+    /// The message is only formatted when a debugger is attached.
+    pub fn log_point<F: FnOnce() -> String>(file: &str, line: u32, message: F) {
+        if !hooks_enabled() {
+            return;
+        }
+        // This may be the program's first hook, so attach before checking
+        init();
+        if !crate::communication::is_attached() {
+            return;
+        }
+
+        if let Err(e) = crate::communication::notify_log(file, line, &message()) {
+            eprintln!("[DBUG] Error sending log message: {}", e);
+        }
+
+        poll_debugger(|| format!("logging at {}:{}", file, line));
+    }
+
     /// Register a variable with the debugger
     pub fn register_variable(
         name: &str,
@@ -100,7 +206,101 @@ pub mod _internal {
         value: &str,
         is_mutable: bool,
     ) -> DbugResult<()> {
-        crate::communication::notify_variable_changed(name, type_name, value, is_mutable)
+        if !hooks_enabled() {
+            return Ok(());
+        }
+        init();
+
+        // Keep a copy for conditions and logpoints, which are decided here
+        if crate::communication::is_attached() {
+            crate::runtime::record_variable(name, type_name, value, is_mutable);
+        }
+
+        trace!("Variable {}: {} = {}", name, type_name, value);
+        crate::communication::notify_variable_changed(name, type_name, value, is_mutable)?;
+        crate::communication::poll_debugger(|| format!("registering {}", name))
+    }
+
+    /// Register a variable captured by the instrumentation macros
+    pub fn register_captured(
+        name: &str,
+        type_name: &str,
+        value: Captured,
+        is_mutable: bool,
+    ) -> DbugResult<()> {
+        if !hooks_enabled() {
+            return Ok(());
+        }
+        init();
+
+        let value = match value {
+            Captured::Text(text) => return register_variable(name, type_name, &text, is_mutable),
+            Captured::Structure(value) => value,
+        };
+
+        if crate::communication::is_attached() {
+            crate::runtime::record_variable_value(name, type_name, value.clone(), is_mutable);
+        }
+
+        trace!("Variable {}: {} = {}", name, type_name, value);
+        crate::communication::notify_variable_inspected(name, type_name, &value, is_mutable)?;
+        crate::communication::poll_debugger(|| format!("registering {}", name))
+    }
+
+    /// A value captured by `register_var!` or `#[dbug(args, locals)]`
+    pub enum Captured {
+        /// The value rendered as text
+        Text(String),
+        /// The tree built by `DbugInspect`
+        Structure(VariableValue),
+    }
+
+    /// A value about to be captured
+    ///
+    /// `(&&&Inspect(&value)).inspect_value()` builds the value's tree when it
+    /// implements `DbugInspect`, renders it with `Debug` when it doesn't, and
+    /// falls back to the type name when [`InspectOpaque`] is in scope, so
+    /// tracing locals never fails to compile.
+    pub struct Inspect<'a, T: ?Sized>(pub &'a T);
+
+    /// Captures an [`Inspect`] with `DbugInspect`
+    pub trait InspectStructure {
+        fn inspect_value(&self) -> Captured;
+    }
+
+    impl<T: DbugInspect + ?Sized> InspectStructure for &&Inspect<'_, T> {
+        fn inspect_value(&self) -> Captured {
+            Captured::Structure(self.0.to_variable_value())
+        }
+    }
+
+    /// Renders an [`Inspect`] with `Debug`
+    pub trait InspectDebug {
+        fn inspect_value(&self) -> Captured;
+    }
+
+    impl<T: std::fmt::Debug + ?Sized> InspectDebug for &Inspect<'_, T> {
+        fn inspect_value(&self) -> Captured {
+            Captured::Text(format!("{:?}", self.0))
+        }
+    }
+
+    /// Renders an [`Inspect`] whose value has no `Debug`
+    pub trait InspectOpaque {
+        fn inspect_value(&self) -> Captured;
+    }
+
+    impl<T: ?Sized> InspectOpaque for Inspect<'_, T> {
+        fn inspect_value(&self) -> Captured {
+            Captured::Text(format!("<{}>", std::any::type_name::<T>()))
+        }
+    }
+
+    /// Act on a pause, detach or kill request from the debugger
+    fn poll_debugger<F: FnOnce() -> String>(location: F) {
+        if let Err(e) = crate::communication::poll_debugger(location) {
+            eprintln!("[DBUG] Error polling the debugger: {}", e);
+        }
     }
 
     /// Generate a unique ID for an async task
@@ -108,13 +308,16 @@ pub mod _internal {
         runtime_generate_task_id()
     }
 
-    /// Get the ID of the current async task
+    /// Get the ID of the async task being polled, or `NO_TASK` outside of one
     pub fn get_current_async_task_id() -> TaskId {
         runtime_get_task_id()
     }
 
     /// Called when entering an async function that's marked for debugging
     pub fn enter_async_function(function_name: &str, task_id: TaskId) {
+        if !hooks_enabled() {
+            return;
+        }
         init();
 
         // Get the current file and line number
@@ -134,15 +337,21 @@ pub mod _internal {
             eprintln!("[DBUG] Error registering async task: {}", e);
         }
 
-        // Also log to console in development mode
-        eprintln!(
-            "[DBUG] Entering async function: {} (task_id: {})",
-            function_name, task_id
+        trace!(
+            "Entering async function: {} (task_id: {})",
+            function_name,
+            task_id
         );
+
+        poll_debugger(|| format!("entering {} (task {})", function_name, task_id));
     }
 
     /// Called when exiting an async function that's marked for debugging
     pub fn exit_async_function(function_name: &str, task_id: TaskId) {
+        if !hooks_enabled() {
+            return;
+        }
+
         // Notify the debugger
         if let Err(e) = crate::communication::notify_async_function_exited(function_name, task_id) {
             eprintln!("[DBUG] Error notifying async function exit: {}", e);
@@ -153,36 +362,181 @@ pub mod _internal {
             eprintln!("[DBUG] Error completing async task: {}", e);
         }
 
-        // Also log to console in development mode
-        eprintln!(
-            "[DBUG] Exiting async function: {} (task_id: {})",
-            function_name, task_id
+        trace!(
+            "Exiting async function: {} (task_id: {})",
+            function_name,
+            task_id
         );
+
+        poll_debugger(|| format!("exiting {} (task {})", function_name, task_id));
+    }
+
+    /// Follows the body of a `#[dbug_async]` function poll by poll
+    ///
+    /// The function is entered on the first poll and exited when the body is
+    /// ready. In between, the task is reported as running while it is polled
+    /// and as waiting whenever it returns `Pending`. The task is current on
+    /// the polling thread only during each poll, since it may move between
+    /// threads at every await.
+    ///
+    /// # Panics
+    ///
+    /// Polling it again after it returned `Ready` panics, like polling the
+    /// `async` block it wraps would.
+    pub struct InstrumentedFuture<F> {
+        // Pinned along with the wrapper; dropped in place once the body is
+        // ready, so dropping early can be told apart
+        inner: Option<F>,
+        function_name: &'static str,
+        task_id: TaskId,
+        entered: bool,
+    }
+
+    impl<F: Future> InstrumentedFuture<F> {
+        /// Wrap the body of `function_name`, running as task `task_id`
+        pub fn new(function_name: &'static str, task_id: TaskId, inner: F) -> Self {
+            Self {
+                inner: Some(inner),
+                function_name,
+                task_id,
+                entered: false,
+            }
+        }
+    }
+
+    impl<F: Future> Future for InstrumentedFuture<F> {
+        type Output = F::Output;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+            // SAFETY: `inner` is structurally pinned. It is only reached
+            // through this `Pin`, is never moved out (it is dropped in place,
+            // here and in `Drop`), and the wrapper is only `Unpin` when `F`
+            // is. The other fields are never pinned.
+            let (mut inner, function_name, task_id, entered) = unsafe {
+                let Self {
+                    inner,
+                    function_name,
+                    task_id,
+                    entered,
+                } = self.get_unchecked_mut();
+                (Pin::new_unchecked(inner), *function_name, *task_id, entered)
+            };
+
+            let future = inner
+                .as_mut()
+                .as_pin_mut()
+                .expect("InstrumentedFuture polled after completion");
+            let _current = crate::runtime::async_support::enter_async_task(task_id);
+            if !hooks_enabled() {
+                let poll = future.poll(cx);
+                if poll.is_ready() {
+                    inner.set(None);
+                }
+                return poll;
+            }
+
+            if !*entered {
+                *entered = true;
+                enter_async_function(function_name, task_id);
+            }
+            set_task_state(task_id, AsyncTaskState::Running);
+
+            let poll = future.poll(cx);
+            match poll {
+                Poll::Pending => {
+                    set_task_state(task_id, AsyncTaskState::Waiting);
+                    trace!(
+                        "Async task waiting: {} (task_id: {})",
+                        function_name,
+                        task_id
+                    );
+                }
+                Poll::Ready(_) => {
+                    inner.set(None);
+                    exit_async_function(function_name, task_id);
+                }
+            }
+            poll
+        }
+    }
+
+    impl<F> Drop for InstrumentedFuture<F> {
+        fn drop(&mut self) {
+            // Dropped before it was ready: cancelled, or unwound from a panic.
+            // The body goes first, so anything it drops still runs in the task.
+            // Assigning drops it in place, which keeps it pinned until then.
+            let current = crate::runtime::async_support::enter_async_task(self.task_id);
+            let unfinished = self.inner.is_some();
+            self.inner = None;
+            drop(current);
+            if !unfinished || !self.entered {
+                return;
+            }
+
+            if let Err(e) =
+                crate::communication::notify_async_function_exited(self.function_name, self.task_id)
+            {
+                eprintln!("[DBUG] Error notifying async function exit: {}", e);
+            }
+            if let Err(e) = crate::runtime::async_support::cancel_async_task(self.task_id) {
+                eprintln!("[DBUG] Error cancelling async task: {}", e);
+            }
+
+            trace!(
+                "Async function cancelled: {} (task_id: {})",
+                self.function_name,
+                self.task_id
+            );
+        }
+    }
+
+    /// Update the state of a task, reporting failures like the other hooks
+    fn set_task_state(task_id: TaskId, state: AsyncTaskState) {
+        if let Err(e) = crate::runtime::async_support::update_async_task_state(task_id, state) {
+            eprintln!("[DBUG] Error updating async task state: {}", e);
+        }
+    }
+
+    /// Called by `#[dbug_async]` as each `.await` is reached, passing the awaited future through
+    pub fn awaiting<F>(task_id: TaskId, file: &str, line: u32, expression: &str, future: F) -> F {
+        if hooks_enabled() {
+            crate::runtime::async_support::set_async_task_awaiting(
+                task_id,
+                format!("{}.await at {}:{}", expression, file, line),
+            );
+        }
+        future
     }
 
     /// Called when an async breakpoint is encountered
-    pub fn async_break_point(file: &str, line: u32, column: u32, task_id: TaskId) {
+    ///
+    /// `function` is the path of the enclosing `#[dbug_async]` function, or
+    /// the module path when the breakpoint is outside one.
+    pub fn async_break_point(file: &str, line: u32, column: u32, function: &str, task_id: TaskId) {
+        if !hooks_enabled() {
+            return;
+        }
         init();
 
-        // Use a simpler approach - we'll use the file name to guess the function
-        // In a real implementation, this would use DWARF debug info to get the actual function name
-        let file_stem = std::path::Path::new(file)
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("unknown");
-
-        let current_function = format!("async_function_in_{}", file_stem);
+        // Disabled and unmet breakpoints are decided here, without a round-trip
+        if !crate::runtime::flow_control::should_break_at(file, line) {
+            return;
+        }
 
         // Process the debug point
-        if let Err(e) = crate::communication::process_async_debug_point(file, line, column, task_id)
+        if let Err(e) =
+            crate::communication::process_async_debug_point(file, line, column, function, task_id)
         {
             eprintln!("[DBUG] Error processing async debug point: {}", e);
         }
 
-        // Also log to console in development mode
-        eprintln!(
-            "[DBUG] Async breakpoint at {}:{}:{} in {} (task_id: {})",
-            file, line, column, current_function, task_id
+        trace!(
+            "Async breakpoint at {}:{}:{} in {} (task_id: {})",
+            file,
+            line,
+            column,
+            function,
+            task_id
         );
     }
 }
@@ -1,7 +1,12 @@
 use clap::{Parser, Subcommand};
+use dbug::communication::{
+    BufferFullPolicy, ChannelEvent, DebuggerMessage, DebuggerResponse, SessionRendezvous, Trace,
+    TraceRecorder, TransportKind, ENABLED_ENV, SESSION_TOKEN_ENV,
+};
 use dbug::{self};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{exit, Command};
+use std::time::Duration;
 
 // Temporarily commented out due to compilation issues
 // mod cli;
@@ -10,6 +15,7 @@ use std::process::{exit, Command};
 #[command(name = "dbug")]
 #[command(about = "A CLI-based debugger for Rust projects", long_about = None)]
 #[command(version)]
+#[command(help_template = "{name} {version}\n{about}\n\nUSAGE:\n    {usage}\n\n{all-args}")]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
@@ -41,6 +47,14 @@ enum Commands {
         /// Build in release mode
         #[arg(short, long)]
         release: bool,
+
+        /// Transport used to talk to the program (mmap or unix)
+        #[arg(long, value_name = "KIND", default_value = "mmap")]
+        transport: TransportKind,
+
+        /// What the program does when the channel buffer is full (block or drop)
+        #[arg(long, value_name = "POLICY", default_value = "block")]
+        on_buffer_full: BufferFullPolicy,
     },
 
     /// Build and debug a Rust project
@@ -52,7 +66,48 @@ enum Commands {
         /// Build in release mode
         #[arg(short, long)]
         release: bool,
+
+        /// Transport used to talk to the program (mmap or unix)
+        #[arg(long, value_name = "KIND", default_value = "mmap")]
+        transport: TransportKind,
+
+        /// What the program does when the channel buffer is full (block or drop)
+        #[arg(long, value_name = "POLICY", default_value = "block")]
+        on_buffer_full: BufferFullPolicy,
+
+        /// Let a debugger attach over TCP on this address instead of opening the TUI
+        #[arg(long, value_name = "ADDR", conflicts_with = "transport")]
+        listen: Option<String>,
+
+        /// Record everything the program sends to a trace file for `dbug replay`
+        #[arg(long, value_name = "FILE", conflicts_with = "listen")]
+        record: Option<PathBuf>,
+    },
+
+    /// Attach to a program started with `dbug debug --listen`
+    Attach {
+        /// Where the program listens, e.g. tcp://127.0.0.1:9000
+        #[arg(value_name = "TARGET")]
+        target: String,
+
+        /// Session token printed by `dbug debug --listen` (defaults to $DBUG_SESSION_TOKEN)
+        #[arg(long, value_name = "TOKEN")]
+        token: Option<String>,
+
+        /// Record everything the program sends to a trace file for `dbug replay`
+        #[arg(long, value_name = "FILE")]
+        record: Option<PathBuf>,
     },
+
+    /// Replay a session recorded with `--record` in the TUI
+    Replay {
+        /// The trace file to replay
+        #[arg(value_name = "TRACE")]
+        trace: PathBuf,
+    },
+
+    /// Print version information
+    Version,
 }
 
 fn main() {
@@ -69,14 +124,40 @@ fn main() {
         Commands::Run {
             project_path,
             release,
+            transport,
+            on_buffer_full,
         } => {
-            run_project(project_path, *release);
+            run_project(project_path, *release, *transport, *on_buffer_full);
         }
         Commands::Debug {
             project_path,
             release,
+            transport,
+            on_buffer_full,
+            listen,
+            record,
+        } => {
+            debug_project(
+                project_path,
+                *release,
+                *transport,
+                *on_buffer_full,
+                listen.as_deref(),
+                record.as_deref(),
+            );
+        }
+        Commands::Attach {
+            target,
+            token,
+            record,
         } => {
-            debug_project(project_path, *release);
+            attach_to_program(target, token.as_deref(), record.as_deref());
+        }
+        Commands::Replay { trace } => {
+            replay_trace(trace);
+        }
+        Commands::Version => {
+            println!("dbug v{}", env!("CARGO_PKG_VERSION"));
         }
     }
 }
@@ -145,7 +226,12 @@ fn build_project(project_path: &str, release: bool, _target_dir: Option<&str>) {
     }
 }
 
-fn run_project(project_path: &str, release: bool) {
+fn run_project(
+    project_path: &str,
+    release: bool,
+    transport: TransportKind,
+    buffer_full: BufferFullPolicy,
+) {
     println!("Building and running project at: {}", project_path);
 
     // Build first
@@ -155,10 +241,13 @@ fn run_project(project_path: &str, release: bool) {
     println!("Starting application with debug instrumentation...");
 
     // Initialize the communication channel
-    if let Err(e) = dbug::communication::init_debugging_session() {
-        println!("Error initializing debugging session: {}", e);
-        exit(1);
-    }
+    let rendezvous = match dbug::communication::init_debugging_session(transport, buffer_full) {
+        Ok(rendezvous) => rendezvous,
+        Err(e) => {
+            println!("Error initializing debugging session: {}", e);
+            exit(1);
+        }
+    };
 
     // Find the executable path
     let project_name = match dbug::cargo::get_project_name(project_path) {
@@ -173,17 +262,55 @@ fn run_project(project_path: &str, release: bool) {
     let executable_path = dbug::utils::find_executable_path(project_path, &project_name, release);
 
     // Run the executable directly with debugging enabled
-    let status = match Command::new(&executable_path)
-        .env("DBUG_ENABLED", "1")
-        .status()
-    {
-        Ok(status) => status,
+    let mut command = Command::new(&executable_path);
+    command.env(ENABLED_ENV, "1");
+    rendezvous.apply_to_command(&mut command);
+
+    let mut child_process = match command.spawn() {
+        Ok(child) => child,
         Err(e) => {
             println!("Error launching executable: {}", e);
             exit(1);
         }
     };
 
+    // Follow the program's events while it runs
+    let pump = dbug::communication::start_event_pump();
+    let status = loop {
+        match child_process.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => {}
+            Err(e) => {
+                println!("Error waiting for executable: {}", e);
+                exit(1);
+            }
+        }
+
+        match pump.next_timeout(Duration::from_millis(10)) {
+            Some(ChannelEvent::Connected(hello)) => println!(
+                "Program connected: {} (pid {}, dbug {}, protocol {})",
+                hello.executable, hello.pid, hello.crate_version, hello.protocol_version
+            ),
+            Some(ChannelEvent::DecodeError(e)) => println!("Skipped an unreadable message: {}", e),
+            Some(ChannelEvent::Error(e)) => println!("Error communicating with program: {}", e),
+            // Nobody is at the controls, so stops resume right away
+            Some(ChannelEvent::Message(event)) => {
+                if matches!(
+                    event.message,
+                    DebuggerMessage::BreakpointHit { .. }
+                        | DebuggerMessage::AsyncBreakPoint { .. }
+                        | DebuggerMessage::Paused { .. }
+                ) {
+                    if let Err(e) = dbug::communication::send_response(DebuggerResponse::Continue) {
+                        println!("Error resuming program: {}", e);
+                    }
+                }
+            }
+            Some(ChannelEvent::Disconnected) | None => {}
+        }
+    };
+    drop(pump);
+
     // Clean up
     if let Err(e) = dbug::communication::cleanup_debugging_session() {
         println!("Error cleaning up debugging session: {}", e);
@@ -198,7 +325,14 @@ fn run_project(project_path: &str, release: bool) {
     }
 }
 
-fn debug_project(project_path: &str, release: bool) {
+fn debug_project(
+    project_path: &str,
+    release: bool,
+    transport: TransportKind,
+    buffer_full: BufferFullPolicy,
+    listen: Option<&str>,
+    record: Option<&Path>,
+) {
     println!("Debugging project at: {}", project_path);
 
     // Check if the project is valid
@@ -243,13 +377,24 @@ fn debug_project(project_path: &str, release: bool) {
     // Use the utility function to find the correct executable path
     let executable_path = dbug::utils::find_executable_path(project_path, &project_name, release);
 
+    // A remote debugger drives the program instead of the local TUI
+    if let Some(address) = listen {
+        serve_program(&executable_path, address, buffer_full);
+        return;
+    }
+
     println!("Starting debugger for: {}", executable_path.display());
 
+    let recorder = create_recorder(record);
+
     // Initialize the communication channel
-    if let Err(e) = dbug::communication::init_debugging_session() {
-        println!("Error initializing debugging session: {}", e);
-        exit(1);
-    }
+    let rendezvous = match dbug::communication::init_debugging_session(transport, buffer_full) {
+        Ok(rendezvous) => rendezvous,
+        Err(e) => {
+            println!("Error initializing debugging session: {}", e);
+            exit(1);
+        }
+    };
 
     // Set the executable in the session
     if let Ok(session) = dbug::session::get_current_session() {
@@ -260,10 +405,11 @@ fn debug_project(project_path: &str, release: bool) {
     }
 
     // Launch the executable in a separate process
-    let child_process = match std::process::Command::new(&executable_path)
-        .env("DBUG_ENABLED", "1") // Signal to the program that it's being debugged
-        .spawn()
-    {
+    let mut command = Command::new(&executable_path);
+    command.env(ENABLED_ENV, "1"); // Signal to the program that it's being debugged
+    rendezvous.apply_to_command(&mut command); // Tell it which channel to attach to
+
+    let child_process = match command.spawn() {
         Ok(child) => child,
         Err(e) => {
             println!("Error launching executable: {}", e);
@@ -287,7 +433,7 @@ fn debug_project(project_path: &str, release: bool) {
     }
 
     // Launch the TUI
-    match dbug::tui::run() {
+    match dbug::tui::run(recorder) {
         Ok(_) => println!("TUI session completed"),
         Err(e) => println!("Error running TUI: {}", e),
     }
@@ -306,3 +452,133 @@ fn debug_project(project_path: &str, release: bool) {
 
     println!("Debugging session ended.");
 }
+
+/// Run the program and let a debugger attach to it over TCP
+fn serve_program(executable_path: &Path, address: &str, buffer_full: BufferFullPolicy) {
+    let rendezvous = match SessionRendezvous::create(TransportKind::Tcp, buffer_full) {
+        Ok(rendezvous) => rendezvous.listening_on(address),
+        Err(e) => {
+            println!("Error initializing debugging session: {}", e);
+            exit(1);
+        }
+    };
+
+    println!("Program will wait for a debugger on {}", address);
+    println!(
+        "Attach with: dbug attach tcp://{} --token {}",
+        address, rendezvous.token
+    );
+
+    let mut command = Command::new(executable_path);
+    command.env(ENABLED_ENV, "1");
+    rendezvous.apply_to_command(&mut command);
+
+    let status = command.status();
+
+    // Clean up
+    if let Err(e) = rendezvous.remove() {
+        println!("Error cleaning up debugging session: {}", e);
+    }
+
+    match status {
+        Ok(status) if status.success() => println!("Debugging session ended."),
+        Ok(status) => {
+            println!(
+                "Error: Program failed with exit code: {}",
+                status.code().unwrap_or(1)
+            );
+            exit(status.code().unwrap_or(1));
+        }
+        Err(e) => {
+            println!("Error launching executable: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn attach_to_program(target: &str, token: Option<&str>, record: Option<&Path>) {
+    let address = match target.strip_prefix("tcp://") {
+        Some(address) => address,
+        None if !target.contains("://") => target,
+        None => {
+            println!(
+                "Error: Unsupported attach target '{}' (expected tcp://HOST:PORT)",
+                target
+            );
+            exit(1);
+        }
+    };
+
+    let token = match token
+        .map(str::to_string)
+        .or_else(|| std::env::var(SESSION_TOKEN_ENV).ok())
+    {
+        Some(token) => token,
+        None => {
+            println!(
+                "Error: No session token given (use --token or ${})",
+                SESSION_TOKEN_ENV
+            );
+            exit(1);
+        }
+    };
+
+    let recorder = create_recorder(record);
+
+    println!("Attaching to: {}", address);
+
+    if let Err(e) = dbug::communication::connect_to_debuggee(address, &token) {
+        println!("Error attaching to program: {}", e);
+        exit(1);
+    }
+
+    // Launch the TUI
+    match dbug::tui::run(recorder) {
+        Ok(_) => println!("TUI session completed"),
+        Err(e) => println!("Error running TUI: {}", e),
+    }
+
+    // Clean up
+    if let Err(e) = dbug::communication::cleanup_debugging_session() {
+        println!("Error cleaning up debugging session: {}", e);
+    }
+
+    println!("Debugging session ended.");
+}
+
+/// Create the trace file a session is recorded to, if one was asked for
+fn create_recorder(record: Option<&Path>) -> Option<TraceRecorder> {
+    let path = record?;
+
+    match TraceRecorder::create(path) {
+        Ok(recorder) => {
+            println!("Recording session to: {}", path.display());
+            Some(recorder)
+        }
+        Err(e) => {
+            println!("Error creating trace file: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn replay_trace(path: &Path) {
+    let trace = match Trace::load(path) {
+        Ok(trace) => trace,
+        Err(e) => {
+            println!("Error loading trace: {}", e);
+            exit(1);
+        }
+    };
+
+    println!(
+        "Replaying {} recorded events from: {}",
+        trace.events.len(),
+        path.display()
+    );
+
+    match dbug::tui::replay(trace) {
+        Ok(_) => println!("Replay completed"),
+        Err(e) => println!("Error running TUI: {}", e),
+    }
+}
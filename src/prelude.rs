@@ -5,13 +5,16 @@ pub use dbug_macros::async_break_here;
 pub use dbug_macros::async_break_when;
 pub use dbug_macros::break_at;
 pub use dbug_macros::break_here;
+pub use dbug_macros::break_when;
 pub use dbug_macros::dbug;
 pub use dbug_macros::dbug_async;
+pub use dbug_macros::log_point;
 pub use dbug_macros::register_var;
+pub use dbug_macros::DbugInspect;
 
 // Re-export runtime types that might be useful in user code
 pub use crate::runtime::async_support::{AsyncTaskInfo, AsyncTaskState, TaskId};
-pub use crate::runtime::{Variable, VariableValue};
+pub use crate::runtime::{DbugInspect, Variable, VariableValue};
 
 // Re-export the register_variable function
 pub use crate::_internal::register_variable;
@@ -7,6 +7,9 @@ use std::sync::RwLock;
 /// A unique identifier for async tasks
 pub type TaskId = u64;
 
+/// The id reported outside of any instrumented task
+pub const NO_TASK: TaskId = 0;
+
 /// Represents the state of an async task
 #[derive(Debug, Clone)]
 pub struct AsyncTaskInfo {
@@ -20,6 +23,8 @@ pub struct AsyncTaskInfo {
     pub state: AsyncTaskState,
     /// The parent task that spawned this task (if any)
     pub parent_id: Option<TaskId>,
+    /// The last `.await` the task reached, such as `fetch(url).await at src/main.rs:12`
+    pub awaiting: Option<String>,
 }
 
 /// Represents the possible states of an async task
@@ -37,13 +42,14 @@ pub enum AsyncTaskState {
     Cancelled,
 }
 
-// Thread-local storage for the current task ID
+// The task being polled on this thread, set only for the duration of a poll
+// since work-stealing executors move tasks between threads at every await
 thread_local! {
     static CURRENT_TASK_ID: std::cell::Cell<Option<TaskId>> = const { std::cell::Cell::new(None) };
 }
 
-// A global counter for generating unique task IDs
-static NEXT_TASK_ID: AtomicU64 = AtomicU64::new(1);
+// A global counter for generating unique task IDs, starting after `NO_TASK`
+static NEXT_TASK_ID: AtomicU64 = AtomicU64::new(NO_TASK + 1);
 
 // A global registry of all active async tasks
 lazy_static::lazy_static! {
@@ -52,21 +58,14 @@ lazy_static::lazy_static! {
 
 /// Generate a new unique task ID
 pub fn generate_async_task_id() -> TaskId {
-    let id = NEXT_TASK_ID.fetch_add(1, Ordering::SeqCst);
-    CURRENT_TASK_ID.with(|cell| cell.set(Some(id)));
-    id
+    NEXT_TASK_ID.fetch_add(1, Ordering::SeqCst)
 }
 
-/// Get the ID of the current async task
+/// Get the ID of the async task being polled on this thread
+///
+/// Returns `NO_TASK` outside of an instrumented task.
 pub fn get_current_async_task_id() -> TaskId {
-    CURRENT_TASK_ID.with(|cell| {
-        cell.get().unwrap_or_else(|| {
-            // If there's no task ID set, generate a new one
-            let id = generate_async_task_id();
-            cell.set(Some(id));
-            id
-        })
-    })
+    CURRENT_TASK_ID.with(|cell| cell.get()).unwrap_or(NO_TASK)
 }
 
 /// Set the current task ID for this thread
@@ -79,6 +78,26 @@ pub fn clear_current_async_task_id() {
     CURRENT_TASK_ID.with(|cell| cell.set(None));
 }
 
+/// Makes a task current on this thread until it is dropped
+///
+/// The task that was current before is restored, so an instrumented future
+/// polled from inside another one doesn't change the outer task's id.
+pub struct CurrentTaskGuard {
+    previous: Option<TaskId>,
+}
+
+/// Make `task_id` the current task on this thread while the guard lives
+pub fn enter_async_task(task_id: TaskId) -> CurrentTaskGuard {
+    let previous = CURRENT_TASK_ID.with(|cell| cell.replace(Some(task_id)));
+    CurrentTaskGuard { previous }
+}
+
+impl Drop for CurrentTaskGuard {
+    fn drop(&mut self) {
+        CURRENT_TASK_ID.with(|cell| cell.set(self.previous));
+    }
+}
+
 /// Register a new async task
 pub fn register_async_task(
     function_name: &str,
@@ -91,6 +110,7 @@ pub fn register_async_task(
         created_at: std::time::Instant::now(),
         state: AsyncTaskState::Created,
         parent_id,
+        awaiting: None,
     };
 
     // Register the task
@@ -109,11 +129,18 @@ pub fn update_async_task_state(task_id: TaskId, state: AsyncTaskState) -> DbugRe
         let old_state = task.state.clone();
         task.state = state.clone();
 
+        // Only a waiting task is blocked on its await
+        let awaiting = match state {
+            AsyncTaskState::Waiting => task.awaiting.as_deref(),
+            _ => None,
+        };
+
         // Notify the debugger
         communication::notify_async_task_state_changed(
             task_id,
             &old_state.to_string(),
             &state.to_string(),
+            awaiting,
         )
     } else {
         // Task not found, might have been garbage collected
@@ -121,11 +148,27 @@ pub fn update_async_task_state(task_id: TaskId, state: AsyncTaskState) -> DbugRe
     }
 }
 
+/// Record the `.await` an async task reached
+///
+/// The task is reported as blocked on it if it goes on to wait.
+pub fn set_async_task_awaiting(task_id: TaskId, awaiting: String) {
+    let mut registry = ASYNC_TASK_REGISTRY.write().unwrap();
+
+    if let Some(task) = registry.get_mut(&task_id) {
+        task.awaiting = Some(awaiting);
+    }
+}
+
 /// Mark an async task as completed
 pub fn complete_async_task(task_id: TaskId) -> DbugResult<()> {
     update_async_task_state(task_id, AsyncTaskState::Completed)
 }
 
+/// Mark an async task as dropped before it completed
+pub fn cancel_async_task(task_id: TaskId) -> DbugResult<()> {
+    update_async_task_state(task_id, AsyncTaskState::Cancelled)
+}
+
 /// Get information about an async task
 pub fn get_async_task_info(task_id: TaskId) -> Option<AsyncTaskInfo> {
     let registry = ASYNC_TASK_REGISTRY.read().unwrap();
@@ -173,13 +216,18 @@ fn visualize_task(
     // Add indentation based on depth
     let indent = "  ".repeat(depth);
 
-    // Add this task
+    // Add this task, with what it's waiting for
+    let awaiting = match (&task.state, &task.awaiting) {
+        (AsyncTaskState::Waiting, Some(awaiting)) => format!(" on {}", awaiting),
+        _ => String::new(),
+    };
     result.push_str(&format!(
-        "{}└─ Task {} ({}): {} [{}]\n",
+        "{}└─ Task {} ({}): {}{} [{}]\n",
         indent,
         task.id,
         task.function_name,
         task.state,
+        awaiting,
         humantime::format_duration(task.created_at.elapsed())
     ));
 
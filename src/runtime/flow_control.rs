@@ -1,10 +1,13 @@
 // Flow control functionality for the runtime debugger
+use crate::communication::BreakpointSpec;
 use crate::errors::DbugResult;
-use crate::runtime::variables::VariableValue;
-use crate::runtime::Breakpoint;
+use crate::runtime::variables::{VariableInspector, VariableValue};
+use crate::runtime::{Breakpoint, BreakpointConditionMode, HitCountCondition};
 use once_cell::sync::Lazy;
-use std::sync::atomic::AtomicU32;
-use std::sync::RwLock;
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::thread::ThreadId;
 
 /// The current execution state of the debugger
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -384,6 +387,247 @@ impl BreakpointManager {
 static BREAKPOINT_MANAGER: Lazy<RwLock<BreakpointManager>> =
     Lazy::new(|| RwLock::new(BreakpointManager::new()));
 
+// Number of breakpoints the manager holds, checked without taking its lock
+static INSTALLED_BREAKPOINTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Remove all breakpoints known to the flow controller
+pub fn clear_breakpoints() {
+    if let Ok(mut manager) = BREAKPOINT_MANAGER.write() {
+        manager.breakpoints.clear();
+        INSTALLED_BREAKPOINTS.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Replace the breakpoints with the table the debugger pushed
+///
+/// Hit counts of breakpoints that stay at the same location are kept.
+pub fn install_breakpoints(specs: &[BreakpointSpec]) {
+    let mut manager = match BREAKPOINT_MANAGER.write() {
+        Ok(manager) => manager,
+        Err(_) => return,
+    };
+
+    let mut breakpoints = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let hit_count = match spec.hit_count.as_deref() {
+            Some(expression) => match HitCountCondition::from_string(expression) {
+                Some(condition) => Some(condition),
+                None => {
+                    eprintln!(
+                        "[DBUG] Ignoring breakpoint at {}:{} with invalid hit count '{}'",
+                        spec.file, spec.line, expression
+                    );
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        let id = manager.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut breakpoint = Breakpoint::new(&spec.file, spec.line, 0, id);
+        breakpoint.enabled = spec.enabled;
+        breakpoint.log_message = spec.log_message.clone();
+        breakpoint.condition_mode = match (spec.condition.as_deref(), hit_count) {
+            (Some(expression), Some(hit_count)) => BreakpointConditionMode::Combined {
+                expression: expression.to_string(),
+                hit_count,
+            },
+            (Some(expression), None) => {
+                BreakpointConditionMode::ConditionalExpression(expression.to_string())
+            }
+            (None, Some(hit_count)) => BreakpointConditionMode::HitCount(hit_count),
+            (None, None) => BreakpointConditionMode::Always,
+        };
+
+        if let Some(previous) = manager
+            .breakpoints
+            .iter()
+            .find(|bp| bp.is_at_location(&spec.file, spec.line))
+        {
+            breakpoint.hit_count = previous.hit_count;
+            breakpoint.last_hit = previous.last_hit;
+        }
+
+        breakpoints.push(breakpoint);
+    }
+
+    INSTALLED_BREAKPOINTS.store(breakpoints.len(), Ordering::Relaxed);
+    manager.breakpoints = breakpoints;
+}
+
+/// Whether a debug point at this location should stop and ask the debugger what to do
+///
+/// Locations the debugger did not list always stop. Listed ones stop only
+/// while enabled and when their condition and hit count are met, which is
+/// decided here without a round-trip to the debugger. Logpoints only log.
+pub fn should_break_at(file: &str, line: u32) -> bool {
+    // Stepping stops the stepping thread at every debug point
+    if crate::runtime::get_flow_control() != FlowControl::Continue && steps_on_this_thread() {
+        return true;
+    }
+
+    listed_breakpoint_triggers(file, line).unwrap_or(true)
+}
+
+/// Whether a statement checkpoint should stop
+///
+/// Unlike explicit debug points, checkpoints only stop where stepping ends
+/// or where the debugger set a breakpoint on the line.
+pub fn should_stop_at_checkpoint(file: &str, line: u32) -> bool {
+    step_ends_here() || listed_breakpoint_triggers(file, line).unwrap_or(false)
+}
+
+/// Check the debugger's breakpoint at this location, if it set one
+///
+/// A logpoint sends its message from here and never stops.
+fn listed_breakpoint_triggers(file: &str, line: u32) -> Option<bool> {
+    if INSTALLED_BREAKPOINTS.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+
+    let mut manager = match BREAKPOINT_MANAGER.write() {
+        Ok(manager) => manager,
+        Err(_) => return None,
+    };
+
+    let breakpoint = manager
+        .breakpoints
+        .iter_mut()
+        .find(|bp| bp.is_at_location(file, line))?;
+
+    if !breakpoint.enabled {
+        return Some(false);
+    }
+    breakpoint.register_hit();
+
+    // Only conditions and log messages need the variables in scope
+    let variables = match (&breakpoint.condition_mode, &breakpoint.log_message) {
+        (BreakpointConditionMode::ConditionalExpression(_), _)
+        | (BreakpointConditionMode::Combined { .. }, _)
+        | (_, Some(_)) => crate::runtime::get_current_variables().unwrap_or_default(),
+        _ => VariableInspector::default(),
+    };
+
+    if !breakpoint.should_trigger(&variables) {
+        return Some(false);
+    }
+
+    // Logpoints report and let the program carry on
+    let Some(template) = breakpoint.log_message.clone() else {
+        return Some(true);
+    };
+    drop(manager);
+
+    let message = crate::runtime::format_log_message(&template, &variables);
+    if let Err(e) = crate::communication::notify_log(file, line, &message) {
+        eprintln!("[DBUG] Error sending log message: {}", e);
+    }
+    Some(false)
+}
+
+thread_local! {
+    // Number of instrumented functions this thread is inside of
+    static CALL_DEPTH: Cell<u32> = const { Cell::new(0) };
+
+    // Names of the `#[dbug]` functions this thread is inside of, outermost first
+    static FUNCTION_STACK: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Where the program last stopped; stepping is measured from here
+struct StepOrigin {
+    thread: ThreadId,
+    depth: u32,
+}
+
+static STEP_ORIGIN: Mutex<Option<StepOrigin>> = Mutex::new(None);
+
+/// Record that the current thread entered an instrumented function
+pub fn enter_call() {
+    CALL_DEPTH.with(|depth| depth.set(depth.get() + 1));
+}
+
+/// Record that the current thread left an instrumented function
+pub fn exit_call() {
+    CALL_DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+}
+
+/// How many instrumented functions the current thread is inside of
+pub fn call_depth() -> u32 {
+    CALL_DEPTH.with(Cell::get)
+}
+
+/// Record that the current thread entered the `#[dbug]` function `name`
+pub fn push_function(name: &'static str) {
+    FUNCTION_STACK.with(|stack| stack.borrow_mut().push(name));
+}
+
+/// Record that the current thread left the innermost `#[dbug]` function
+pub fn pop_function() {
+    FUNCTION_STACK.with(|stack| stack.borrow_mut().pop());
+}
+
+/// The `#[dbug]` functions the current thread is inside of, innermost first
+pub fn function_stack() -> Vec<String> {
+    FUNCTION_STACK.with(|stack| {
+        stack
+            .borrow()
+            .iter()
+            .rev()
+            .map(|name| name.to_string())
+            .collect()
+    })
+}
+
+/// Remember that the current thread stopped, so the next step is relative to here
+pub fn mark_stop() {
+    if let Ok(mut origin) = STEP_ORIGIN.lock() {
+        *origin = Some(StepOrigin {
+            thread: std::thread::current().id(),
+            depth: call_depth(),
+        });
+    }
+}
+
+/// Whether the step the debugger asked for ends at the current point
+///
+/// Step into ends at the next statement anywhere, step over at the next
+/// statement that isn't in a deeper call, and step out at the next one in a
+/// caller. Only the thread that stopped steps; others keep running.
+pub fn step_ends_here() -> bool {
+    let flow = crate::runtime::get_flow_control();
+    if flow == FlowControl::Continue {
+        return false;
+    }
+
+    let origin = match STEP_ORIGIN.lock() {
+        Ok(origin) => origin,
+        Err(_) => return true,
+    };
+    let Some(origin) = origin.as_ref() else {
+        return true;
+    };
+    if origin.thread != std::thread::current().id() {
+        return false;
+    }
+
+    let depth = call_depth();
+    match flow {
+        FlowControl::StepOver => depth <= origin.depth,
+        FlowControl::StepOut => depth < origin.depth,
+        _ => true,
+    }
+}
+
+/// Whether the current thread is the one that stopped, and so the one stepping
+fn steps_on_this_thread() -> bool {
+    match STEP_ORIGIN.lock() {
+        Ok(origin) => origin
+            .as_ref()
+            .map_or(true, |origin| origin.thread == std::thread::current().id()),
+        Err(_) => true,
+    }
+}
+
 /// Process an async debug point (breakpoint in async code)
 pub fn handle_async_breakpoint(
     file: &str,
@@ -0,0 +1,38 @@
+// Deciding whether the instrumentation hooks do anything
+//
+// An instrumented program run on its own should cost next to nothing, so
+// the environment is read once and every hook checks the result before
+// doing any work.
+
+use crate::communication::{ENABLED_ENV, LOG_ENV, SESSION_DIR_ENV};
+use once_cell::sync::Lazy;
+use std::env;
+
+/// What this process was asked to do with its instrumentation
+struct Gate {
+    /// Started by the debugger, or told to act as if it was
+    debugger: bool,
+    /// Print every hook to stderr
+    trace: bool,
+}
+
+static GATE: Lazy<Gate> = Lazy::new(|| Gate {
+    debugger: env::var(ENABLED_ENV).is_ok_and(|value| !value.is_empty() && value != "0")
+        || env::var_os(SESSION_DIR_ENV).is_some(),
+    trace: env::var(LOG_ENV).is_ok_and(|value| value.eq_ignore_ascii_case("trace")),
+});
+
+/// Whether the program was started for debugging, with `DBUG_ENABLED` or a session
+pub fn debugger_enabled() -> bool {
+    GATE.debugger
+}
+
+/// Whether `DBUG_LOG=trace` asked for hooks to be printed
+pub fn trace_enabled() -> bool {
+    GATE.trace
+}
+
+/// Whether the instrumentation hooks should run at all
+pub fn hooks_enabled() -> bool {
+    GATE.debugger || GATE.trace
+}
@@ -0,0 +1,258 @@
+// Structured inspection of values for the debugger
+//
+// `DbugInspect` turns a value into a `VariableValue` tree, so the debugger
+// can show field names, enum variants and collection sizes instead of a
+// flat `Debug` string. It is implemented here for common std types and can
+// be derived with `#[derive(DbugInspect)]` for user types.
+
+use super::variables::VariableValue;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Maximum number of elements captured from a collection
+///
+/// Lengths and capacities are still reported in full.
+pub const MAX_INSPECTED_ELEMENTS: usize = 100;
+
+/// A value that can describe its structure to the debugger
+pub trait DbugInspect {
+    /// Build the tree the debugger shows for this value
+    fn to_variable_value(&self) -> VariableValue;
+}
+
+macro_rules! inspect_integers {
+    ($($ty:ty),*) => {
+        $(
+            impl DbugInspect for $ty {
+                fn to_variable_value(&self) -> VariableValue {
+                    match i64::try_from(*self) {
+                        Ok(i) => VariableValue::Integer(i),
+                        Err(_) => VariableValue::String(self.to_string()),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+inspect_integers!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+macro_rules! inspect_floats {
+    ($($ty:ty),*) => {
+        $(
+            impl DbugInspect for $ty {
+                fn to_variable_value(&self) -> VariableValue {
+                    // NaN and infinities don't survive the trip through JSON
+                    if self.is_finite() {
+                        VariableValue::Float(*self as f64)
+                    } else {
+                        VariableValue::String(self.to_string())
+                    }
+                }
+            }
+        )*
+    };
+}
+
+inspect_floats!(f32, f64);
+
+impl DbugInspect for bool {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::Boolean(*self)
+    }
+}
+
+impl DbugInspect for char {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::Char(*self)
+    }
+}
+
+impl DbugInspect for str {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::String(self.to_string())
+    }
+}
+
+impl DbugInspect for String {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::String(self.clone())
+    }
+}
+
+impl DbugInspect for () {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::Null
+    }
+}
+
+impl<T: DbugInspect> DbugInspect for Option<T> {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::Option(
+            self.as_ref()
+                .map(|value| Box::new(value.to_variable_value())),
+        )
+    }
+}
+
+impl<T: DbugInspect, E: DbugInspect> DbugInspect for Result<T, E> {
+    fn to_variable_value(&self) -> VariableValue {
+        let (variant, value) = match self {
+            Ok(value) => ("Ok", value.to_variable_value()),
+            Err(error) => ("Err", error.to_variable_value()),
+        };
+        let fields = HashMap::from([("0".to_string(), value)]);
+        VariableValue::new_complex("Result", variant, fields, None)
+    }
+}
+
+impl<T: DbugInspect + ?Sized> DbugInspect for &T {
+    fn to_variable_value(&self) -> VariableValue {
+        (**self).to_variable_value()
+    }
+}
+
+impl<T: DbugInspect + ?Sized> DbugInspect for &mut T {
+    fn to_variable_value(&self) -> VariableValue {
+        (**self).to_variable_value()
+    }
+}
+
+impl<T: DbugInspect + ?Sized> DbugInspect for Box<T> {
+    fn to_variable_value(&self) -> VariableValue {
+        (**self).to_variable_value()
+    }
+}
+
+impl<T: DbugInspect + ?Sized> DbugInspect for Rc<T> {
+    fn to_variable_value(&self) -> VariableValue {
+        (**self).to_variable_value()
+    }
+}
+
+impl<T: DbugInspect + ?Sized> DbugInspect for Arc<T> {
+    fn to_variable_value(&self) -> VariableValue {
+        (**self).to_variable_value()
+    }
+}
+
+/// Capture the first elements of a collection
+fn inspect_elements<'a, T: DbugInspect + 'a>(
+    items: impl IntoIterator<Item = &'a T>,
+) -> Vec<VariableValue> {
+    items
+        .into_iter()
+        .take(MAX_INSPECTED_ELEMENTS)
+        .map(DbugInspect::to_variable_value)
+        .collect()
+}
+
+/// Capture the first entries of a map
+fn inspect_entries<'a, K: DbugInspect + 'a, V: DbugInspect + 'a>(
+    entries: impl IntoIterator<Item = (&'a K, &'a V)>,
+) -> Vec<(VariableValue, VariableValue)> {
+    entries
+        .into_iter()
+        .take(MAX_INSPECTED_ELEMENTS)
+        .map(|(key, value)| (key.to_variable_value(), value.to_variable_value()))
+        .collect()
+}
+
+impl<T: DbugInspect> DbugInspect for [T] {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::Array(inspect_elements(self))
+    }
+}
+
+impl<T: DbugInspect, const N: usize> DbugInspect for [T; N] {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::Array(inspect_elements(self))
+    }
+}
+
+impl<T: DbugInspect> DbugInspect for Vec<T> {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::Vec {
+            elements: inspect_elements(self),
+            length: self.len(),
+            capacity: self.capacity(),
+        }
+    }
+}
+
+impl<T: DbugInspect> DbugInspect for VecDeque<T> {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::Vec {
+            elements: inspect_elements(self),
+            length: self.len(),
+            capacity: self.capacity(),
+        }
+    }
+}
+
+impl<T: DbugInspect, S> DbugInspect for HashSet<T, S> {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::new_complex(
+            "HashSet",
+            &format!("len: {}, capacity: {}", self.len(), self.capacity()),
+            HashMap::new(),
+            Some(inspect_elements(self)),
+        )
+    }
+}
+
+impl<T: DbugInspect> DbugInspect for BTreeSet<T> {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::new_complex(
+            "BTreeSet",
+            &format!("len: {}", self.len()),
+            HashMap::new(),
+            Some(inspect_elements(self)),
+        )
+    }
+}
+
+impl<K: DbugInspect, V: DbugInspect, S> DbugInspect for HashMap<K, V, S> {
+    fn to_variable_value(&self) -> VariableValue {
+        VariableValue::HashMap {
+            entries: inspect_entries(self),
+            size: self.len(),
+            capacity: self.capacity(),
+        }
+    }
+}
+
+impl<K: DbugInspect, V: DbugInspect> DbugInspect for BTreeMap<K, V> {
+    fn to_variable_value(&self) -> VariableValue {
+        // A BTreeMap has no spare capacity to report
+        VariableValue::HashMap {
+            entries: inspect_entries(self),
+            size: self.len(),
+            capacity: self.len(),
+        }
+    }
+}
+
+macro_rules! inspect_tuples {
+    ($(($($name:ident),+)),*) => {
+        $(
+            impl<$($name: DbugInspect),+> DbugInspect for ($($name,)+) {
+                #[allow(non_snake_case)]
+                fn to_variable_value(&self) -> VariableValue {
+                    let ($($name,)+) = self;
+                    VariableValue::Array(vec![$($name.to_variable_value()),+])
+                }
+            }
+        )*
+    };
+}
+
+inspect_tuples!(
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F)
+);
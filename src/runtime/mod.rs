@@ -2,10 +2,13 @@
 
 pub mod async_support;
 pub mod flow_control;
+pub mod gate;
+pub mod inspect;
 pub mod type_visualization;
 pub mod variables;
 
 pub use flow_control::{ExecutionPoint, ExecutionState, FlowControl, FlowController};
+pub use inspect::DbugInspect;
 pub use type_visualization::TypeVisualizer;
 pub use variables::{ChangeStatus, Variable, VariableInspector, VariableValue};
 
@@ -77,6 +80,12 @@ pub fn get_flow_control() -> FlowControl {
     }
 }
 
+/// Removes every breakpoint known to the runtime
+pub fn clear_all_breakpoints() {
+    get_global_runtime().clear_breakpoints();
+    flow_control::clear_breakpoints();
+}
+
 /// Gets the current variables in scope
 pub fn get_current_variables() -> DbugResult<VariableInspector> {
     // This is a simplified implementation that uses a global runtime
@@ -85,6 +94,52 @@ pub fn get_current_variables() -> DbugResult<VariableInspector> {
     Ok(runtime.variable_inspector.clone())
 }
 
+/// Records a variable the program registered, so conditions and logpoints can see it
+pub fn record_variable(name: &str, type_name: &str, value: &str, is_mutable: bool) {
+    record_variable_value(
+        name,
+        type_name,
+        VariableValue::from_debug(value),
+        is_mutable,
+    );
+}
+
+/// Records a variable whose structure the program captured with `DbugInspect`
+pub fn record_variable_value(name: &str, type_name: &str, value: VariableValue, is_mutable: bool) {
+    let variable = Variable::new(name, type_name, value, 0, is_mutable);
+    get_global_runtime().register_variable(variable);
+}
+
+/// Fills in a logpoint message, replacing each `{expression}` with its value
+///
+/// `{{` and `}}` stand for literal braces.
+pub fn format_log_message(template: &str, variables: &VariableInspector) -> String {
+    let mut message = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                message.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                message.push('}');
+            }
+            '{' => {
+                let expression: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                if let Some(value) = evaluate_expression(expression.trim(), variables) {
+                    message.push_str(&value);
+                }
+            }
+            c => message.push(c),
+        }
+    }
+
+    message
+}
+
 /// Evaluates an expression in the current context
 pub fn evaluate_expression(expression: &str, variables: &VariableInspector) -> Option<String> {
     // Create a temporary watch and evaluate it
@@ -103,31 +158,28 @@ fn evaluate_member_access_helper(base_var: &Variable, members: &[&str]) -> Optio
     let member = members[0];
 
     // Handle the member access based on the base variable's type
-    match &base_var.value {
-        VariableValue::Struct(fields) => {
-            if let Some(field_value) = fields.get(member) {
-                if members.len() == 1 {
-                    // This is the last member, return its value
-                    return Some(field_value.to_string());
-                } else {
-                    // Create a temporary variable for the field
-                    let temp_var = Variable::new(
-                        member,
-                        "field",
-                        field_value.clone(),
-                        base_var.scope_level,
-                        false,
-                    );
-
-                    // Recursively evaluate the next member
-                    return evaluate_member_access_helper(&temp_var, &members[1..]);
-                }
+    if let VariableValue::Struct(fields) = &base_var.value {
+        if let Some(field_value) = fields.get(member) {
+            if members.len() == 1 {
+                // This is the last member, return its value
+                return Some(field_value.to_string());
+            } else {
+                // Create a temporary variable for the field
+                let temp_var = Variable::new(
+                    member,
+                    "field",
+                    field_value.clone(),
+                    base_var.scope_level,
+                    false,
+                );
+
+                // Recursively evaluate the next member
+                return evaluate_member_access_helper(&temp_var, &members[1..]);
             }
         }
-        // For other types, we don't support member access
-        _ => {}
     }
 
+    // For other types, we don't support member access
     None
 }
 
@@ -218,6 +270,8 @@ pub struct Breakpoint {
     pub created_at: std::time::Instant,
     /// When this breakpoint was last hit
     pub last_hit: Option<std::time::Instant>,
+    /// Message to log instead of stopping, for logpoints
+    pub log_message: Option<String>,
 }
 
 impl Breakpoint {
@@ -233,9 +287,16 @@ impl Breakpoint {
             id,
             created_at: std::time::Instant::now(),
             last_hit: None,
+            log_message: None,
         }
     }
 
+    /// Log a message instead of stopping
+    pub fn with_log_message(mut self, message: &str) -> Self {
+        self.log_message = Some(message.to_string());
+        self
+    }
+
     /// Set a condition for the breakpoint
     pub fn with_condition(mut self, condition: &str) -> Self {
         self.condition_mode = BreakpointConditionMode::ConditionalExpression(condition.to_string());
@@ -448,15 +509,11 @@ impl WatchExpression {
     /// Evaluate array access expressions
     fn evaluate_array_access(&self, var: &Variable, index: usize) -> Option<String> {
         match &var.value {
-            VariableValue::Array(elements) => {
-                if index < elements.len() {
-                    return Some(elements[index].to_string());
-                }
+            VariableValue::Array(elements) if index < elements.len() => {
+                return Some(elements[index].to_string());
             }
-            VariableValue::Vec { elements, .. } => {
-                if index < elements.len() {
-                    return Some(elements[index].to_string());
-                }
+            VariableValue::Vec { elements, .. } if index < elements.len() => {
+                return Some(elements[index].to_string());
             }
             _ => {}
         }
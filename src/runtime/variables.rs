@@ -1,5 +1,6 @@
 // Variable inspection functionality for the runtime debugger
 
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 
@@ -7,22 +8,18 @@ use std::fmt;
 pub const MAX_VISUALIZATION_DEPTH: usize = 3;
 
 /// Represents the value of a variable
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum VariableValue {
     Integer(i64),
     Float(f64),
     Boolean(bool),
     String(String),
     Char(char),
-    #[allow(dead_code)]
     Array(Vec<VariableValue>),
-    #[allow(dead_code)]
     Struct(HashMap<String, VariableValue>),
-    #[allow(dead_code)]
     Option(Option<Box<VariableValue>>),
     #[allow(dead_code)]
     Reference(Box<VariableValue>),
-    #[allow(dead_code)]
     Null,
     /// Enhanced type for complex data structures
     Complex {
@@ -95,7 +92,7 @@ impl VariableValue {
                     write!(f, "...({})", fields.len())?;
                 } else {
                     let mut first = true;
-                    for (key, val) in fields {
+                    for (key, val) in sorted_fields(fields) {
                         if !first {
                             write!(f, ", ")?;
                         }
@@ -131,7 +128,7 @@ impl VariableValue {
                     if !fields.is_empty() {
                         write!(f, " {{")?;
                         let mut first = true;
-                        for (key, val) in fields {
+                        for (key, val) in sorted_fields(fields) {
                             if !first {
                                 write!(f, ", ")?;
                             }
@@ -215,6 +212,79 @@ impl VariableValue {
         }
     }
 
+    /// Read back a value rendered with `{:?}`
+    ///
+    /// Numbers, booleans, chars and strings get their own variants; anything
+    /// else is kept as the rendered text.
+    pub fn from_debug(text: &str) -> Self {
+        if let Ok(i) = text.parse::<i64>() {
+            return VariableValue::Integer(i);
+        }
+        // NaN and infinities don't survive the trip through JSON, so they stay text
+        if let Some(fl) = text.parse::<f64>().ok().filter(|fl| fl.is_finite()) {
+            return VariableValue::Float(fl);
+        }
+        if let Ok(b) = text.parse::<bool>() {
+            return VariableValue::Boolean(b);
+        }
+
+        let unquoted = |quote: char| {
+            text.strip_prefix(quote)
+                .and_then(|rest| rest.strip_suffix(quote))
+        };
+        if let Some(c) = unquoted('\'').and_then(|c| c.parse::<char>().ok()) {
+            return VariableValue::Char(c);
+        }
+        match unquoted('"') {
+            Some(s) => VariableValue::String(s.to_string()),
+            None => VariableValue::String(text.to_string()),
+        }
+    }
+
+    /// The fields, variants' contents, elements or entries inside this value
+    ///
+    /// Each comes with the label the debugger shows it under; values with
+    /// nothing inside have no children.
+    pub fn children(&self) -> Vec<(String, &VariableValue)> {
+        fn indexed(elements: &[VariableValue]) -> Vec<(String, &VariableValue)> {
+            elements
+                .iter()
+                .enumerate()
+                .map(|(i, element)| (format!("[{}]", i), element))
+                .collect()
+        }
+
+        match self {
+            VariableValue::Struct(fields) => sorted_fields(fields)
+                .into_iter()
+                .map(|(name, value)| (name.clone(), value))
+                .collect(),
+            VariableValue::Complex {
+                fields, children, ..
+            } => {
+                let mut all: Vec<_> = sorted_fields(fields)
+                    .into_iter()
+                    .map(|(name, value)| (name.clone(), value))
+                    .collect();
+                if let Some(elements) = children {
+                    all.extend(indexed(elements));
+                }
+                all
+            }
+            VariableValue::Array(elements) | VariableValue::Vec { elements, .. } => {
+                indexed(elements)
+            }
+            VariableValue::HashMap { entries, .. } => entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+            VariableValue::Option(Some(value)) | VariableValue::Reference(value) => {
+                value.children()
+            }
+            _ => Vec::new(),
+        }
+    }
+
     /// Create a vector representation
     #[allow(dead_code)]
     pub fn new_vec(elements: Vec<VariableValue>, capacity: usize) -> Self {
@@ -238,7 +308,6 @@ impl VariableValue {
     }
 
     /// Create a complex structure representation
-    #[allow(dead_code)]
     pub fn new_complex(
         type_name: &str,
         summary: &str,
@@ -254,6 +323,19 @@ impl VariableValue {
     }
 }
 
+/// Fields in a stable order, so the same value always looks the same
+pub fn sorted_fields(fields: &HashMap<String, VariableValue>) -> Vec<(&String, &VariableValue)> {
+    let mut sorted: Vec<_> = fields.iter().collect();
+    sorted.sort_by(|(a, _), (b, _)| {
+        // Tuple fields are numbered; keep `10` after `9`
+        match (a.parse::<usize>(), b.parse::<usize>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        }
+    });
+    sorted
+}
+
 /// Variable change status to track modifications
 #[derive(Debug, Clone, PartialEq)]
 pub enum ChangeStatus {
@@ -419,6 +501,7 @@ impl VariableInspector {
     }
 
     /// Compare two variable values for equality
+    #[allow(dead_code)]
     fn values_equal(&self, val1: &VariableValue, val2: &VariableValue) -> bool {
         Self::are_values_equal(val1, val2)
     }
@@ -487,12 +570,10 @@ impl VariableInspector {
                     }
                 }
             }
-            VariableValue::Struct(fields) => {
-                if !fields.is_empty() {
-                    result.push_str("\n  Fields:");
-                    for (name, value) in fields {
-                        result.push_str(&format!("\n    {}: {}", name, value));
-                    }
+            VariableValue::Struct(fields) if !fields.is_empty() => {
+                result.push_str("\n  Fields:");
+                for (name, value) in fields {
+                    result.push_str(&format!("\n    {}: {}", name, value));
                 }
             }
             VariableValue::Complex {
@@ -1,5 +1,13 @@
+use crate::communication::{BreakpointSpec, DebuggerEvent, DebuggerMessage, Hello, Trace};
+use crate::runtime::{HitCountCondition, VariableValue};
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Number of lines kept in the console panel
+const MAX_CONSOLE_LINES: usize = 200;
+
+/// Number of events skipped by PageUp and PageDown while replaying
+const REPLAY_PAGE: usize = 20;
 
 /// Panel types for the UI
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -24,6 +32,68 @@ pub struct App {
     pub debug_state: DebugState,
     /// Selected item in the current panel
     pub selected_item: Option<usize>,
+    /// Lines shown in the console panel, oldest first
+    pub console: Vec<String>,
+    /// Latest type and value of each variable the program reported
+    pub variables: BTreeMap<String, (String, String)>,
+    /// Structure of the variables captured with `DbugInspect`
+    pub variable_structures: BTreeMap<String, VariableValue>,
+    /// Variables whose structure is shown in the variables panel
+    pub expanded_variables: BTreeSet<String>,
+    /// The breakpoint table pushed to the program
+    pub breakpoints: Vec<BreakpointSpec>,
+    /// Location of the last breakpoint the program stopped at
+    pub last_stop: Option<(String, u32)>,
+    /// The `#[dbug]` functions the program was inside of at its last stop, innermost first
+    pub call_stack: Vec<String>,
+    /// Labels of the hard-coded breakpoints the program stopped at, by location
+    pub breakpoint_labels: BTreeMap<(String, u32), String>,
+    /// The recorded session being replayed, if this is not a live session
+    pub replay: Option<Replay>,
+    /// Breakpoint setting being typed at the console prompt
+    pub prompt: Option<Prompt>,
+}
+
+/// A breakpoint setting being typed at the console prompt
+pub struct Prompt {
+    /// The setting being typed
+    pub kind: PromptKind,
+    /// File of the breakpoint it applies to
+    pub file: String,
+    /// Line of the breakpoint it applies to
+    pub line: u32,
+    /// Text typed so far
+    pub text: String,
+}
+
+/// Breakpoint settings that can be typed at the console prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    /// Message logged instead of stopping, turning the breakpoint into a logpoint
+    LogMessage,
+    /// Expression that must be true for the breakpoint to stop
+    Condition,
+    /// Which hits stop, such as `= 5`, `> 10` or `% 2`
+    HitCount,
+}
+
+impl PromptKind {
+    /// Name shown in front of the typed text
+    pub fn label(self) -> &'static str {
+        match self {
+            PromptKind::LogMessage => "log message",
+            PromptKind::Condition => "condition",
+            PromptKind::HitCount => "hit count",
+        }
+    }
+}
+
+/// A recorded session being replayed
+pub struct Replay {
+    /// The recorded session
+    pub trace: Trace,
+    /// Number of recorded events applied so far
+    pub position: usize,
 }
 
 /// Debug session state
@@ -38,6 +108,10 @@ pub enum DebugState {
         line: u32,
         function: String,
     },
+    /// Program stopped because the debugger asked it to pause
+    Interrupted { location: String },
+    /// The program went away
+    Disconnected,
 }
 
 impl Default for App {
@@ -67,9 +141,27 @@ impl App {
             scroll_positions,
             debug_state: DebugState::Idle,
             selected_item: None,
+            console: Vec::new(),
+            variables: BTreeMap::new(),
+            variable_structures: BTreeMap::new(),
+            expanded_variables: BTreeSet::new(),
+            breakpoints: Vec::new(),
+            last_stop: None,
+            call_stack: Vec::new(),
+            breakpoint_labels: BTreeMap::new(),
+            replay: None,
+            prompt: None,
         }
     }
 
+    /// Create an application state replaying a recorded session
+    pub fn replay(trace: Trace) -> Self {
+        let mut app = Self::new();
+        app.replay = Some(Replay { trace, position: 0 });
+        app.seek(0);
+        app
+    }
+
     /// Handle a terminal event
     pub fn handle_event(&mut self, event: Event) {
         if let Event::Key(key) = event {
@@ -79,15 +171,54 @@ impl App {
 
     /// Handle a key event
     fn handle_key_event(&mut self, key: KeyEvent) {
+        if self.replay.is_some() {
+            self.handle_replay_key_event(key);
+            return;
+        }
+        if self.prompt.is_some() {
+            self.handle_prompt_key(key);
+            return;
+        }
+
         match key.code {
-            // Quit application with Ctrl+C or 'q'
+            // Interrupt the program with Ctrl+C
             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.running = false;
+                self.pause_execution();
             }
+
+            // Quit application with 'q', leaving the program running
             KeyCode::Char('q') => {
+                if self.is_connected() {
+                    self.detach_program();
+                }
                 self.running = false;
             }
 
+            // Toggle the breakpoint the program last stopped at
+            KeyCode::Char('b') => self.toggle_breakpoint(),
+
+            // Type a setting for the selected breakpoint at the console prompt
+            KeyCode::Char('l') => self.start_prompt(PromptKind::LogMessage),
+            KeyCode::Char('c') => self.start_prompt(PromptKind::Condition),
+            KeyCode::Char('h') => self.start_prompt(PromptKind::HitCount),
+
+            // Let the program run on its own, or end it
+            KeyCode::Char('d') => self.detach_program(),
+            KeyCode::Char('k') => self.kill_program(),
+
+            // Flow control
+            KeyCode::F(5) => self.continue_execution(),
+            KeyCode::F(10) => self.step_over(),
+            KeyCode::F(11) if key.modifiers.contains(KeyModifiers::SHIFT) => self.step_out(),
+            KeyCode::F(11) => self.step_into(),
+
+            _ => self.handle_navigation_key(key),
+        }
+    }
+
+    /// Handle the panel navigation keys shared by live sessions and replays
+    fn handle_navigation_key(&mut self, key: KeyEvent) {
+        match key.code {
             // Panel navigation with tab
             KeyCode::Tab => {
                 self.active_panel = match self.active_panel {
@@ -114,19 +245,356 @@ impl App {
                 *scroll += 1;
             }
 
-            // TODO: Add debugging control keys once we implement the flow control
+            // Show or hide what's inside the selected variable
+            KeyCode::Enter if self.active_panel == PanelType::Variables => {
+                self.toggle_variable();
+            }
+
+            _ => {}
+        }
+    }
+
+    /// Expand or collapse the variable selected in the variables panel
+    fn toggle_variable(&mut self) {
+        let selected = self.scroll_positions[&PanelType::Variables];
+        let name = match self.variables.keys().nth(selected) {
+            Some(name) => name.clone(),
+            None => return,
+        };
+
+        if !self.expanded_variables.remove(&name) {
+            self.expanded_variables.insert(name);
+        }
+    }
+
+    /// Handle a key event while typing at the console prompt
+    fn handle_prompt_key(&mut self, key: KeyEvent) {
+        let prompt = match &mut self.prompt {
+            Some(prompt) => prompt,
+            None => return,
+        };
+
+        match key.code {
+            KeyCode::Esc => self.prompt = None,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.prompt = None;
+            }
+            KeyCode::Char(c) => prompt.text.push(c),
+            KeyCode::Backspace => {
+                prompt.text.pop();
+            }
+            KeyCode::Enter => {
+                if let Some(prompt) = self.prompt.take() {
+                    self.submit_prompt(prompt);
+                }
+            }
             _ => {}
         }
     }
 
+    /// Locations listed in the breakpoints panel, in order
+    ///
+    /// The breakpoint table comes first, then the labelled breakpoints it doesn't mention.
+    pub fn breakpoint_locations(&self) -> Vec<(String, u32)> {
+        let mut locations: Vec<_> = self
+            .breakpoints
+            .iter()
+            .map(|bp| (bp.file.clone(), bp.line))
+            .collect();
+        for location in self.breakpoint_labels.keys() {
+            if !locations.contains(location) {
+                locations.push(location.clone());
+            }
+        }
+        locations
+    }
+
+    /// The breakpoint selected in the breakpoints panel while it is active,
+    /// or else the one the program last stopped at
+    fn selected_breakpoint(&self) -> Option<(String, u32)> {
+        if self.active_panel == PanelType::Breakpoints {
+            let selected = self.scroll_positions[&PanelType::Breakpoints];
+            if let Some(location) = self.breakpoint_locations().into_iter().nth(selected) {
+                return Some(location);
+            }
+        }
+        self.last_stop.clone()
+    }
+
+    /// Start typing a setting for the selected breakpoint, beginning with its current value
+    fn start_prompt(&mut self, kind: PromptKind) {
+        let (file, line) = match self.selected_breakpoint() {
+            Some(location) => location,
+            None => {
+                self.log("No breakpoint selected".to_string());
+                return;
+            }
+        };
+
+        let current = self
+            .breakpoints
+            .iter()
+            .find(|bp| bp.file == file && bp.line == line);
+        let text = match kind {
+            PromptKind::LogMessage => current.and_then(|bp| bp.log_message.clone()),
+            PromptKind::Condition => current.and_then(|bp| bp.condition.clone()),
+            PromptKind::HitCount => current.and_then(|bp| bp.hit_count.clone()),
+        };
+
+        self.prompt = Some(Prompt {
+            kind,
+            file,
+            line,
+            text: text.unwrap_or_default(),
+        });
+    }
+
+    /// Push the setting typed at the console prompt, clearing it if nothing was typed
+    fn submit_prompt(&mut self, prompt: Prompt) {
+        let text = prompt.text.trim();
+        let value = (!text.is_empty()).then(|| text.to_string());
+
+        // The program would ignore the whole breakpoint over a bad hit count
+        if let (PromptKind::HitCount, Some(hit_count)) = (prompt.kind, &value) {
+            if HitCountCondition::from_string(hit_count).is_none() {
+                self.log(format!(
+                    "Invalid hit count '{}', expected '= N', '> N' or '% N'",
+                    hit_count
+                ));
+                return;
+            }
+        }
+
+        let description = match &value {
+            Some(value) => format!("{} set to '{}'", prompt.kind.label(), value),
+            None => format!("{} cleared", prompt.kind.label()),
+        };
+        let result = crate::communication::update_breakpoint(&prompt.file, prompt.line, |bp| {
+            match prompt.kind {
+                PromptKind::LogMessage => bp.log_message = value,
+                PromptKind::Condition => bp.condition = value,
+                PromptKind::HitCount => bp.hit_count = value,
+            }
+        });
+
+        match result {
+            Ok(breakpoint) => {
+                self.log(format!(
+                    "Breakpoint at {}:{}: {}",
+                    prompt.file, prompt.line, description
+                ));
+                match self
+                    .breakpoints
+                    .iter_mut()
+                    .find(|bp| bp.file == prompt.file && bp.line == prompt.line)
+                {
+                    Some(existing) => *existing = breakpoint,
+                    None => self.breakpoints.push(breakpoint),
+                }
+            }
+            Err(e) => self.log(format!("Failed to update breakpoint: {}", e)),
+        }
+    }
+
+    /// Handle a key event while replaying a recorded session
+    fn handle_replay_key_event(&mut self, key: KeyEvent) {
+        let (position, length) = match &self.replay {
+            Some(replay) => (replay.position, replay.trace.events.len()),
+            None => return,
+        };
+
+        match key.code {
+            KeyCode::Char('q') => self.running = false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.running = false;
+            }
+
+            // Scrub through the recorded events
+            KeyCode::Right => self.seek(position + 1),
+            KeyCode::Left => self.seek(position.saturating_sub(1)),
+            KeyCode::PageDown => self.seek(position + REPLAY_PAGE),
+            KeyCode::PageUp => self.seek(position.saturating_sub(REPLAY_PAGE)),
+            KeyCode::Home => self.seek(0),
+            KeyCode::End => self.seek(length),
+
+            // Jump between breakpoint hits
+            KeyCode::Char(']') => {
+                let next = self.find_stop(position + 1..=length);
+                self.seek(next.unwrap_or(length));
+            }
+            KeyCode::Char('[') => {
+                let previous = self.find_stop((1..position).rev());
+                self.seek(previous.unwrap_or(0));
+            }
+
+            _ => self.handle_navigation_key(key),
+        }
+    }
+
+    /// First position in `positions` right after a breakpoint hit or pause
+    fn find_stop(&self, mut positions: impl Iterator<Item = usize>) -> Option<usize> {
+        let events = &self.replay.as_ref()?.trace.events;
+        positions.find(|&position| {
+            matches!(
+                events[position - 1].message,
+                DebuggerMessage::BreakpointHit { .. }
+                    | DebuggerMessage::AsyncBreakPoint { .. }
+                    | DebuggerMessage::Paused { .. }
+            )
+        })
+    }
+
+    /// Show the replayed session as it was after the first `position` events
+    fn seek(&mut self, position: usize) {
+        let replay = match self.replay.take() {
+            Some(replay) => replay,
+            None => return,
+        };
+        let position = position.min(replay.trace.events.len());
+
+        self.debug_state = DebugState::Idle;
+        self.console.clear();
+        self.variables.clear();
+        self.variable_structures.clear();
+        self.call_stack.clear();
+        self.breakpoint_labels.clear();
+
+        if let Some(hello) = &replay.trace.hello {
+            self.connected(hello);
+        }
+        for event in &replay.trace.events[..position] {
+            self.apply_event(event);
+        }
+
+        self.replay = Some(Replay { position, ..replay });
+    }
+
+    /// Update application state for a program that completed the handshake
+    pub fn connected(&mut self, hello: &Hello) {
+        self.log(format!(
+            "Connected to {} (pid {}, dbug {})",
+            hello.executable, hello.pid, hello.crate_version
+        ));
+        self.debug_state = DebugState::Running;
+    }
+
+    /// Update application state for a single event from the program
+    pub fn apply_event(&mut self, event: &DebuggerEvent) {
+        let thread = match &event.thread.name {
+            Some(name) => name.clone(),
+            None => format!("thread {}", event.thread.id),
+        };
+        self.log(format!(
+            "{:>10.3}s [{}] {}",
+            event.timestamp().as_secs_f64(),
+            thread,
+            describe(&event.message)
+        ));
+
+        match &event.message {
+            DebuggerMessage::BreakpointHit {
+                file,
+                line,
+                function,
+                label,
+                call_stack,
+                ..
+            } => {
+                if let Some(label) = label {
+                    self.breakpoint_labels
+                        .insert((file.clone(), *line), label.clone());
+                }
+                self.call_stack = call_stack.clone();
+                self.breakpoint_hit(file.clone(), *line, function.clone());
+            }
+            DebuggerMessage::AsyncBreakPoint {
+                file,
+                line,
+                function,
+                ..
+            } => {
+                // Async tasks don't report the functions around them
+                self.call_stack.clear();
+                self.breakpoint_hit(file.clone(), *line, function.clone());
+            }
+            DebuggerMessage::Paused { location } => {
+                self.debug_state = DebugState::Interrupted {
+                    location: location.clone(),
+                };
+            }
+            DebuggerMessage::VariableChanged {
+                name,
+                type_name,
+                value,
+                structure,
+                ..
+            } => {
+                self.variables
+                    .insert(name.clone(), (type_name.clone(), value.clone()));
+                match structure {
+                    Some(structure) => {
+                        self.variable_structures
+                            .insert(name.clone(), structure.clone());
+                    }
+                    None => {
+                        self.variable_structures.remove(name);
+                    }
+                }
+            }
+            _ => {
+                // Other message types, including function entry and exit,
+                // are only logged; the program keeps running through them
+            }
+        }
+    }
+
     /// Update application state
     pub fn update(&mut self) {
         // This method will be called on each loop iteration
         // It can be used to update application state or poll for debug events
     }
 
+    /// Add a line to the console panel
+    pub fn log(&mut self, line: String) {
+        self.console.push(line);
+        if self.console.len() > MAX_CONSOLE_LINES {
+            self.console.remove(0);
+        }
+    }
+
+    /// Enable or disable the breakpoint the program last stopped at
+    fn toggle_breakpoint(&mut self) {
+        let (file, line) = match &self.last_stop {
+            Some(stop) => stop.clone(),
+            None => {
+                self.log("No breakpoint to toggle yet".to_string());
+                return;
+            }
+        };
+
+        match crate::communication::toggle_breakpoint(&file, line) {
+            Ok(enabled) => {
+                let state = if enabled { "enabled" } else { "disabled" };
+                self.log(format!("Breakpoint at {}:{} {}", file, line, state));
+
+                match self
+                    .breakpoints
+                    .iter_mut()
+                    .find(|bp| bp.file == file && bp.line == line)
+                {
+                    Some(breakpoint) => breakpoint.enabled = enabled,
+                    None => self
+                        .breakpoints
+                        .push(BreakpointSpec::new(&file, line, enabled)),
+                }
+            }
+            Err(e) => self.log(format!("Failed to toggle breakpoint: {}", e)),
+        }
+    }
+
     /// Update application state with breakpoint information
     pub fn breakpoint_hit(&mut self, file: String, line: u32, function: String) {
+        self.last_stop = Some((file.clone(), line));
         self.debug_state = DebugState::Paused {
             file,
             line,
@@ -134,12 +602,59 @@ impl App {
         };
     }
 
+    /// Ask the running program to stop at its next instrumentation point
+    fn pause_execution(&mut self) {
+        if !matches!(self.debug_state, DebugState::Running) {
+            return;
+        }
+
+        match crate::communication::request_pause() {
+            Ok(()) => self.log("Pausing...".to_string()),
+            Err(e) => self.log(format!("Failed to pause: {}", e)),
+        }
+    }
+
+    /// Whether a program is connected to the debugger
+    fn is_connected(&self) -> bool {
+        !matches!(
+            self.debug_state,
+            DebugState::Idle | DebugState::Disconnected
+        )
+    }
+
+    /// Let the program carry on without the debugger
+    fn detach_program(&mut self) {
+        if !self.is_connected() {
+            return;
+        }
+
+        match crate::communication::request_detach(false) {
+            Ok(()) => {
+                self.log("Detached from the program".to_string());
+                self.debug_state = DebugState::Disconnected;
+            }
+            Err(e) => self.log(format!("Failed to detach: {}", e)),
+        }
+    }
+
+    /// Terminate the program
+    fn kill_program(&mut self) {
+        if !self.is_connected() {
+            return;
+        }
+
+        match crate::communication::request_kill() {
+            Ok(()) => self.log("Killing the program...".to_string()),
+            Err(e) => self.log(format!("Failed to kill the program: {}", e)),
+        }
+    }
+
     /// Continue execution until the next breakpoint
     fn continue_execution(&mut self) {
         // Create and send a continue response
         let response = crate::communication::DebuggerResponse::Continue;
         if let Err(e) = crate::communication::send_response(response) {
-            eprintln!("Failed to send continue command: {}", e);
+            self.log(format!("Failed to send continue command: {}", e));
             return;
         }
 
@@ -151,8 +666,12 @@ impl App {
         // Create and send a step over response
         let response = crate::communication::DebuggerResponse::StepOver;
         if let Err(e) = crate::communication::send_response(response) {
-            eprintln!("Failed to send step over command: {}", e);
+            self.log(format!("Failed to send step over command: {}", e));
+            return;
         }
+
+        // The program runs until the step ends at its next stop
+        self.debug_state = DebugState::Running;
     }
 
     /// Step into a function
@@ -160,8 +679,12 @@ impl App {
         // Create and send a step into response
         let response = crate::communication::DebuggerResponse::StepInto;
         if let Err(e) = crate::communication::send_response(response) {
-            eprintln!("Failed to send step into command: {}", e);
+            self.log(format!("Failed to send step into command: {}", e));
+            return;
         }
+
+        // The program runs until the step ends at its next stop
+        self.debug_state = DebugState::Running;
     }
 
     /// Step out of the current function
@@ -169,7 +692,69 @@ impl App {
         // Create and send a step out response
         let response = crate::communication::DebuggerResponse::StepOut;
         if let Err(e) = crate::communication::send_response(response) {
-            eprintln!("Failed to send step out command: {}", e);
+            self.log(format!("Failed to send step out command: {}", e));
+            return;
+        }
+
+        // The program runs until the step ends at its next stop
+        self.debug_state = DebugState::Running;
+    }
+}
+
+/// One-line description of a message for the console
+fn describe(message: &DebuggerMessage) -> String {
+    match message {
+        DebuggerMessage::BreakpointHit {
+            file,
+            line,
+            function,
+            label: Some(label),
+            ..
+        } => format!(
+            "breakpoint '{}' at {}:{} in {}",
+            label, file, line, function
+        ),
+        DebuggerMessage::BreakpointHit {
+            file,
+            line,
+            function,
+            ..
+        } => format!("breakpoint at {}:{} in {}", file, line, function),
+        DebuggerMessage::AsyncBreakPoint {
+            file,
+            line,
+            function,
+            task_id,
+            ..
+        } => format!(
+            "breakpoint at {}:{} in {} (task {})",
+            file, line, function, task_id
+        ),
+        DebuggerMessage::FunctionEntered {
+            function,
+            file,
+            line,
+        } => format!("entered {} ({}:{})", function, file, line),
+        DebuggerMessage::FunctionExited {
+            function,
+            return_value: Some(value),
+        } => format!("exited {} -> {}", function, value),
+        DebuggerMessage::FunctionExited { function, .. } => format!("exited {}", function),
+        DebuggerMessage::VariableChanged {
+            name,
+            type_name,
+            value,
+            ..
+        } => format!("{}: {} = {}", name, type_name, value),
+        DebuggerMessage::ExpressionResult { expression, result } => {
+            format!("{} = {}", expression, result)
         }
+        DebuggerMessage::Paused { location } => format!("paused while {}", location),
+        DebuggerMessage::Log {
+            file,
+            line,
+            message,
+        } => format!("log {}:{}: {}", file, line, message),
+        other => format!("{:?}", other),
     }
 }
@@ -7,13 +7,33 @@ use crate::prelude::*;
 use std::time::Duration;
 
 /// Initialize and run the TUI application
-pub fn run() -> DbugResult<()> {
+///
+/// With a `recorder`, everything the program sends is also written to a trace.
+pub fn run(mut recorder: Option<crate::communication::TraceRecorder>) -> DbugResult<()> {
+    // Read debugger messages in the background
+    let pump = crate::communication::start_event_pump();
+
+    run_app(app::App::new(), |app| {
+        // Apply everything the debugged program sent since the last frame
+        while let Some(event) = pump.try_next() {
+            if let Some(recorder) = recorder.as_mut() {
+                record_channel_event(recorder, &event, app);
+            }
+            handle_channel_event(app, event);
+        }
+    })
+}
+
+/// Replay a recorded session in the TUI
+pub fn replay(trace: crate::communication::Trace) -> DbugResult<()> {
+    run_app(app::App::replay(trace), |_| {})
+}
+
+/// Run the main loop, calling `poll` once per frame
+fn run_app(mut app: app::App, mut poll: impl FnMut(&mut app::App)) -> DbugResult<()> {
     // Initialize terminal
     let mut terminal = terminal::setup_terminal()?;
 
-    // Create app state
-    let mut app = app::App::new();
-
     // Create event handler
     let mut events = event::Events::new(Duration::from_millis(100));
 
@@ -27,52 +47,7 @@ pub fn run() -> DbugResult<()> {
             app.handle_event(event);
         }
 
-        // Check for debugger messages
-        match crate::communication::check_for_messages() {
-            Ok(Some(msg)) => {
-                match msg {
-                    crate::communication::DebuggerMessage::BreakpointHit {
-                        file,
-                        line,
-                        column: _,
-                        function,
-                    } => {
-                        app.breakpoint_hit(file, line, function);
-                    }
-                    crate::communication::DebuggerMessage::FunctionEntered {
-                        function,
-                        file,
-                        line,
-                    } => {
-                        // Update the current function
-                        app.debug_state = app::DebugState::Paused {
-                            file,
-                            line,
-                            function,
-                        };
-                    }
-                    crate::communication::DebuggerMessage::FunctionExited { function: _ } => {
-                        // Just note the function exit
-                    }
-                    crate::communication::DebuggerMessage::VariableChanged {
-                        name: _,
-                        type_name: _,
-                        value: _,
-                        is_mutable: _,
-                    } => {
-                        // Update variables
-                        // (We'll implement this later in a more complete version)
-                    }
-                    _ => {
-                        // Other message types
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("Error checking for messages: {}", e);
-            }
-            _ => {}
-        }
+        poll(&mut app);
 
         // Update state
         app.update();
@@ -83,3 +58,39 @@ pub fn run() -> DbugResult<()> {
 
     Ok(())
 }
+
+/// Write something that happened on the communication channel to the trace
+fn record_channel_event(
+    recorder: &mut crate::communication::TraceRecorder,
+    event: &crate::communication::ChannelEvent,
+    app: &mut app::App,
+) {
+    use crate::communication::ChannelEvent;
+
+    let result = match event {
+        ChannelEvent::Connected(hello) => recorder.record_connected(hello),
+        ChannelEvent::Message(event) => recorder.record_event(event),
+        ChannelEvent::Disconnected => recorder.flush(),
+        ChannelEvent::DecodeError(_) | ChannelEvent::Error(_) => Ok(()),
+    };
+
+    if let Err(e) = result {
+        app.log(format!("Failed to record the session: {}", e));
+    }
+}
+
+/// Update the app for something that happened on the communication channel
+fn handle_channel_event(app: &mut app::App, event: crate::communication::ChannelEvent) {
+    use crate::communication::ChannelEvent;
+
+    match event {
+        ChannelEvent::Connected(hello) => app.connected(&hello),
+        ChannelEvent::Message(event) => app.apply_event(&event),
+        ChannelEvent::DecodeError(e) => app.log(format!("Skipped an unreadable message: {}", e)),
+        ChannelEvent::Error(e) => app.log(format!("Communication error: {}", e)),
+        ChannelEvent::Disconnected => {
+            app.log("The program disconnected".to_string());
+            app.debug_state = app::DebugState::Disconnected;
+        }
+    }
+}
@@ -1,9 +1,11 @@
+use crate::runtime::variables::MAX_VISUALIZATION_DEPTH;
+use crate::runtime::VariableValue;
 use crate::tui::app::{App, DebugState, PanelType};
 use std::vec::Vec;
 use tui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
     Frame,
 };
 
@@ -136,6 +138,8 @@ fn draw_source_panel(f: &mut Frame, app: &App, area: Rect) {
     let text = match &app.debug_state {
         DebugState::Running => "Program is running...".to_string(),
         DebugState::Idle => "No program is running".to_string(),
+        DebugState::Disconnected => "The program has disconnected".to_string(),
+        DebugState::Interrupted { location } => format!("Paused while {}", location),
         _ => unreachable!(),
     };
 
@@ -149,18 +153,45 @@ fn draw_variable_panel(f: &mut Frame, app: &App, area: Rect) {
     let title = "Variables";
     let block = create_block(title, app.active_panel == PanelType::Variables);
 
-    // Placeholder content - would be replaced with actual variable data
-    let items = vec![
-        ListItem::new("var1 = 42"),
-        ListItem::new("var2 = \"Hello, World!\""),
-        ListItem::new("var3 = [1, 2, 3]"),
-    ];
+    // Expanded variables are followed by one line per field or element
+    let selected = app.scroll_positions[&PanelType::Variables];
+    let mut state = ListState::default();
+    let mut lines = Vec::new();
+    for (index, (name, (type_name, value))) in app.variables.iter().enumerate() {
+        let structure = app.variable_structures.get(name);
+        let marker = match structure {
+            Some(_) if app.expanded_variables.contains(name) => "- ",
+            Some(_) => "+ ",
+            None => "  ",
+        };
+        if index == selected && app.active_panel == PanelType::Variables {
+            state.select(Some(lines.len()));
+        }
+        lines.push(format!("{}{}: {} = {}", marker, name, type_name, value));
 
+        if let Some(structure) = structure {
+            if app.expanded_variables.contains(name) {
+                structure_lines(structure, 1, &mut lines);
+            }
+        }
+    }
+
+    let items: Vec<ListItem> = lines.into_iter().map(ListItem::new).collect();
     let list = List::new(items)
         .block(block)
         .highlight_style(Style::default().add_modifier(Modifier::BOLD));
 
-    f.render_widget(list, area);
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+/// Add a line for each field or element inside a value, indented by depth
+fn structure_lines(value: &VariableValue, depth: usize, lines: &mut Vec<String>) {
+    for (label, child) in value.children() {
+        lines.push(format!("{}{}: {}", "  ".repeat(depth + 1), label, child));
+        if depth < MAX_VISUALIZATION_DEPTH {
+            structure_lines(child, depth + 1, lines);
+        }
+    }
 }
 
 /// Draw the call stack panel
@@ -168,12 +199,24 @@ fn draw_call_stack_panel(f: &mut Frame, app: &App, area: Rect) {
     let title = "Call Stack";
     let block = create_block(title, app.active_panel == PanelType::CallStack);
 
-    // Placeholder content
-    let items = vec![
-        ListItem::new("main() at main.rs:10"),
-        ListItem::new("foo() at foo.rs:25"),
-        ListItem::new("bar() at bar.rs:42"),
-    ];
+    // Only the innermost frame's location is known
+    let items: Vec<ListItem> = match &app.debug_state {
+        DebugState::Paused {
+            file,
+            line,
+            function,
+        } => {
+            let mut frames = app.call_stack.iter();
+            if app.call_stack.first() == Some(function) {
+                frames.next();
+            }
+            std::iter::once(format!("{}() at {}:{}", function, file, line))
+                .chain(frames.map(|function| format!("{}()", function)))
+                .map(ListItem::new)
+                .collect()
+        }
+        _ => Vec::new(),
+    };
 
     let list = List::new(items)
         .block(block)
@@ -206,35 +249,103 @@ fn draw_breakpoints_panel(f: &mut Frame, app: &App, area: Rect) {
     let title = "Breakpoints";
     let block = create_block(title, app.active_panel == PanelType::Breakpoints);
 
-    // Placeholder content
-    let items = vec![
-        ListItem::new("main.rs:15"),
-        ListItem::new("foo.rs:42 (condition: x > 10)"),
-        ListItem::new("bar.rs:23 (hit count: 2/3)"),
-    ];
+    let label = |file: &str, line: u32| {
+        app.breakpoint_labels
+            .get(&(file.to_string(), line))
+            .map(|label| format!(" [{}]", label))
+            .unwrap_or_default()
+    };
+
+    let mut items: Vec<ListItem> = app
+        .breakpoints
+        .iter()
+        .map(|bp| {
+            let mut item = format!("{}:{}{}", bp.file, bp.line, label(&bp.file, bp.line));
+            if !bp.enabled {
+                item.push_str(" (disabled)");
+            }
+            if let Some(condition) = &bp.condition {
+                item.push_str(&format!(" (condition: {})", condition));
+            }
+            if let Some(hit_count) = &bp.hit_count {
+                item.push_str(&format!(" (hit count: {})", hit_count));
+            }
+            if let Some(message) = &bp.log_message {
+                item.push_str(&format!(" (log: {})", message));
+            }
+            ListItem::new(item)
+        })
+        .collect();
+
+    // Labelled breakpoints in the code that the table doesn't mention yet
+    items.extend(
+        app.breakpoint_labels
+            .iter()
+            .filter(|((file, line), _)| {
+                !app.breakpoints
+                    .iter()
+                    .any(|bp| &bp.file == file && bp.line == *line)
+            })
+            .map(|((file, line), label)| ListItem::new(format!("{}:{} [{}]", file, line, label))),
+    );
+
+    let selected = app.scroll_positions[&PanelType::Breakpoints];
+    let mut state = ListState::default();
+    if app.active_panel == PanelType::Breakpoints && selected < items.len() {
+        state.select(Some(selected));
+    }
 
     let list = List::new(items)
         .block(block)
         .highlight_style(Style::default().add_modifier(Modifier::BOLD));
 
-    f.render_widget(list, area);
+    f.render_stateful_widget(list, area, &mut state);
 }
 
 /// Draw the console panel
 fn draw_console_panel(f: &mut Frame, app: &App, area: Rect) {
-    let title = "Console";
-    let block = create_block(title, app.active_panel == PanelType::Console);
+    let title = match &app.replay {
+        Some(replay) => format!(
+            "Replay: event {}/{}",
+            replay.position,
+            replay.trace.events.len()
+        ),
+        None => "Console".to_string(),
+    };
+    let block = create_block(&title, app.active_panel == PanelType::Console);
 
-    // Placeholder content
-    let text = "Welcome to dbug!\nPress 'q' to quit, Tab to switch panels.\n> ";
+    // Show the most recent lines that fit below the welcome text
+    let mut lines = vec!["Welcome to dbug!".to_string()];
+    if app.replay.is_some() {
+        lines.push("Left/Right step, [/] jump between stops, Home/End, 'q' to quit.".to_string());
+    } else {
+        lines.push(
+            "Press 'q' to quit, Tab to switch panels, 'c'/'h'/'l' to set a breakpoint's \
+             condition, hit count or log message."
+                .to_string(),
+        );
+    }
+    let visible = (area.height as usize).saturating_sub(lines.len() + 3);
+    let skipped = app.console.len().saturating_sub(visible);
+    lines.extend(app.console.iter().skip(skipped).cloned());
+    match &app.prompt {
+        Some(prompt) => lines.push(format!(
+            "{}:{} {}> {}",
+            prompt.file,
+            prompt.line,
+            prompt.kind.label(),
+            prompt.text
+        )),
+        None => lines.push("> ".to_string()),
+    }
 
-    let paragraph = Paragraph::new(text).block(block);
+    let paragraph = Paragraph::new(lines.join("\n")).block(block);
 
     f.render_widget(paragraph, area);
 }
 
 /// Create a block with the given title and highlight if active
-fn create_block(title: &str, active: bool) -> Block {
+fn create_block(title: &str, active: bool) -> Block<'_> {
     let style = if active {
         Style::default().fg(Color::Cyan)
     } else {
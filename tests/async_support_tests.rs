@@ -44,6 +44,9 @@ async fn test_async_function(input: u32) -> u32 {
 
 #[tokio::test]
 async fn test_async_debugging() {
+    // Act as if started by the debugger, so the hooks track tasks
+    std::env::set_var(dbug::communication::ENABLED_ENV, "1");
+
     // Test a single async function
     let result = test_async_function(5).await;
 
@@ -73,6 +76,9 @@ async fn test_async_debugging() {
 // Test the async task tracking functionality
 #[tokio::test]
 async fn test_async_task_tracking() {
+    // Act as if started by the debugger, so the hooks track tasks
+    std::env::set_var(dbug::communication::ENABLED_ENV, "1");
+
     // Create and run several async tasks
     let mut handles = Vec::new();
 
@@ -108,3 +114,92 @@ async fn test_async_task_tracking() {
         completed_count
     );
 }
+
+#[dbug_async]
+async fn wait_for(rx: tokio::sync::oneshot::Receiver<u32>) -> u32 {
+    rx.await.unwrap_or_default()
+}
+
+/// The `wait_for` task that is waiting, if it has been polled yet
+fn waiting_task() -> Option<AsyncTaskInfo> {
+    dbug::runtime::async_support::get_all_async_tasks()
+        .into_iter()
+        .find(|task| {
+            task.function_name.ends_with("::wait_for") && task.state == AsyncTaskState::Waiting
+        })
+}
+
+// Test that tasks report each poll, and the await they are blocked on
+#[tokio::test]
+async fn test_tasks_report_what_they_wait_for() {
+    // Act as if started by the debugger, so the hooks track tasks
+    std::env::set_var(dbug::communication::ENABLED_ENV, "1");
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let handle = tokio::spawn(wait_for(rx));
+
+    let mut task = None;
+    for _ in 0..100 {
+        task = waiting_task();
+        if task.is_some() {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+    let task = task.expect("wait_for never reported waiting");
+    let awaiting = task.awaiting.unwrap();
+    assert!(
+        awaiting.starts_with("rx.await at tests/async_support_tests.rs:"),
+        "unexpected await: {}",
+        awaiting
+    );
+
+    tx.send(3).unwrap();
+    assert_eq!(handle.await.unwrap(), 3);
+    let state = dbug::runtime::async_support::get_async_task_info(task.id).map(|task| task.state);
+    assert_eq!(state, Some(AsyncTaskState::Completed));
+
+    // A task dropped while it waits was cancelled
+    let (_tx, rx) = tokio::sync::oneshot::channel();
+    let timeout = tokio::time::timeout(Duration::from_millis(10), wait_for(rx)).await;
+    assert!(timeout.is_err());
+    let cancelled = dbug::runtime::async_support::get_all_async_tasks()
+        .into_iter()
+        .any(|task| {
+            task.function_name.ends_with("::wait_for") && task.state == AsyncTaskState::Cancelled
+        });
+    assert!(cancelled);
+}
+
+#[dbug_async]
+async fn observe_task_ids(yields: usize) -> Vec<TaskId> {
+    let mut ids = Vec::new();
+    for _ in 0..yields {
+        ids.push(dbug::_internal::get_current_async_task_id());
+        tokio::task::yield_now().await;
+    }
+    ids
+}
+
+// Test that a task keeps its id as it moves between worker threads
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_task_ids_follow_tasks_across_threads() {
+    // Act as if started by the debugger, so the hooks track tasks
+    std::env::set_var(dbug::communication::ENABLED_ENV, "1");
+
+    let handles: Vec<_> = (0..8).map(|_| tokio::spawn(observe_task_ids(20))).collect();
+
+    let mut seen = std::collections::HashSet::new();
+    for handle in handles {
+        let ids = handle.await.unwrap();
+        assert_ne!(ids[0], dbug::runtime::async_support::NO_TASK);
+        assert!(ids.iter().all(|&id| id == ids[0]), "ids changed: {:?}", ids);
+        assert!(seen.insert(ids[0]), "task id {} reused", ids[0]);
+    }
+
+    // Outside of an instrumented task there is no current task
+    assert_eq!(
+        dbug::_internal::get_current_async_task_id(),
+        dbug::runtime::async_support::NO_TASK
+    );
+}
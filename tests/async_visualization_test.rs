@@ -37,7 +37,7 @@ async fn async_task(id: u32) -> u32 {
     register_var!(id);
 
     // Add a debug point
-    dbug::_internal::async_break_point(file!(), line!(), column!(), task_id);
+    dbug::_internal::async_break_point(file!(), line!(), column!(), "async_task", task_id);
 
     println!("Task {} started", id);
 
@@ -45,7 +45,7 @@ async fn async_task(id: u32) -> u32 {
     sleep(Duration::from_millis(100 * id as u64)).await;
 
     // Another debug point
-    dbug::_internal::async_break_point(file!(), line!(), column!(), task_id);
+    dbug::_internal::async_break_point(file!(), line!(), column!(), "async_task", task_id);
 
     // Return the ID as the result
     println!("Task {} completed", id);
@@ -54,6 +54,9 @@ async fn async_task(id: u32) -> u32 {
 
 #[tokio::test]
 async fn test_async_task_visualization() {
+    // Act as if started by the debugger, so the hooks track tasks
+    std::env::set_var(dbug::communication::ENABLED_ENV, "1");
+
     // Reset the stop flag
     STOP_FLAG.store(false, Ordering::SeqCst);
 
@@ -119,6 +122,10 @@ async fn test_async_task_visualization() {
     let final_tasks = dbug::runtime::async_support::get_all_async_tasks();
     println!("Total tasks created: {}", final_tasks.len());
 
-    // Success if we got this far
-    assert!(true);
+    // Both the sequential and the concurrent tasks should be tracked
+    assert!(
+        final_tasks.len() >= 10,
+        "Expected at least 10 tasks, found {}",
+        final_tasks.len()
+    );
 }
@@ -0,0 +1,738 @@
+//! Tests for the debugger/debuggee communication channel
+
+use dbug::communication::{
+    BufferFullPolicy, CommunicationChannel, DebuggerResponse, SessionRendezvous, TransportKind,
+};
+
+#[test]
+fn test_debuggee_attaches_to_debugger_session() {
+    // The debugger creates the session and its channel files
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+    assert!(rendezvous.message_file_path().exists());
+    assert!(rendezvous.response_file_path().exists());
+
+    // The debuggee attaches to the very same files
+    let mut debuggee = CommunicationChannel::attach(&rendezvous).unwrap();
+    assert!(debuggee.is_active());
+    assert_eq!(debuggee.rendezvous(), Some(&rendezvous));
+
+    // Closing the debuggee must leave the debugger's files in place
+    debuggee.close().unwrap();
+    assert!(rendezvous.dir.exists());
+
+    // Closing the debugger removes the whole session
+    debugger.close().unwrap();
+    assert!(!rendezvous.dir.exists());
+}
+
+#[test]
+fn test_attach_rejects_wrong_token() {
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+
+    let forged = SessionRendezvous {
+        token: "not-the-token".to_string(),
+        ..rendezvous.clone()
+    };
+    assert!(CommunicationChannel::attach(&forged).is_err());
+
+    debugger.close().unwrap();
+}
+
+#[test]
+fn test_channel_without_session_is_inactive() {
+    let channel = CommunicationChannel::disconnected();
+    assert!(!channel.is_active());
+    assert!(channel.rendezvous().is_none());
+}
+
+fn assert_response_round_trip(kind: TransportKind) {
+    let rendezvous = SessionRendezvous::create(kind, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+    let mut debuggee = CommunicationChannel::attach(&rendezvous).unwrap();
+
+    debugger.send_response(&DebuggerResponse::StepOver).unwrap();
+
+    let response = debuggee.wait_for_response().unwrap();
+    assert!(matches!(response, Some(DebuggerResponse::StepOver)));
+
+    debuggee.close().unwrap();
+    debugger.close().unwrap();
+}
+
+#[test]
+fn test_mmap_transport_delivers_responses() {
+    assert_response_round_trip(TransportKind::Mmap);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_transport_delivers_responses() {
+    assert_response_round_trip(TransportKind::UnixSocket);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_transport_detects_disconnect() {
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::UnixSocket, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+    let mut debuggee = CommunicationChannel::attach(&rendezvous).unwrap();
+
+    // Make sure the connection has been accepted before hanging up
+    debugger.send_response(&DebuggerResponse::Continue).unwrap();
+    assert!(debuggee.wait_for_response().unwrap().is_some());
+
+    debugger.close().unwrap();
+
+    let result = debuggee.wait_for_response();
+    assert!(matches!(result, Err(dbug::DbugError::Disconnected)));
+    assert!(!debuggee.is_connected());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_transport_requires_a_private_session_directory() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::UnixSocket, BufferFullPolicy::Block).unwrap();
+    let mode = |dir| std::fs::metadata(dir).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(&rendezvous.dir), 0o700);
+
+    std::fs::set_permissions(&rendezvous.dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(CommunicationChannel::create(&rendezvous).is_err());
+
+    rendezvous.remove().unwrap();
+}
+
+#[test]
+fn test_mmap_transport_detects_disconnect() {
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+    let mut debuggee = CommunicationChannel::attach(&rendezvous).unwrap();
+    assert!(debuggee.is_connected());
+
+    // A response sent just before hanging up is still delivered
+    debugger.send_response(&DebuggerResponse::Continue).unwrap();
+    debugger.close().unwrap();
+
+    assert!(debuggee.wait_for_response().unwrap().is_some());
+    let result = debuggee.wait_for_response();
+    assert!(matches!(result, Err(dbug::DbugError::Disconnected)));
+    assert!(!debuggee.is_connected());
+}
+
+#[test]
+fn test_pause_request_is_picked_up_without_blocking() {
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+    let mut debuggee = CommunicationChannel::attach(&rendezvous).unwrap();
+
+    // Nothing has been requested yet
+    assert!(debuggee.poll_request().unwrap().is_none());
+
+    // A stale answer ahead of the pause request must not hide it
+    debugger.send_response(&DebuggerResponse::Continue).unwrap();
+    debugger.send_response(&DebuggerResponse::Pause).unwrap();
+    assert!(matches!(
+        debuggee.poll_request().unwrap(),
+        Some(DebuggerResponse::Pause)
+    ));
+
+    // The request is consumed once it has been seen
+    assert!(debuggee.poll_request().unwrap().is_none());
+
+    // Detaching wins over a pending pause
+    debugger.send_response(&DebuggerResponse::Pause).unwrap();
+    debugger
+        .send_response(&DebuggerResponse::Detach {
+            clear_breakpoints: true,
+        })
+        .unwrap();
+    assert!(matches!(
+        debuggee.poll_request().unwrap(),
+        Some(DebuggerResponse::Detach {
+            clear_breakpoints: true
+        })
+    ));
+
+    debuggee.close().unwrap();
+    debugger.close().unwrap();
+}
+
+#[test]
+fn test_mmap_ring_buffer_is_lossless() {
+    use dbug::communication::mmap::MmapTransport;
+    use dbug::communication::Transport;
+    use std::time::Duration;
+
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Block).unwrap();
+    let mut debugger = MmapTransport::create(&rendezvous).unwrap();
+    let mut debuggee = MmapTransport::attach(&rendezvous).unwrap();
+
+    // Queue up far more frames than a single slot could hold
+    for i in 0..500 {
+        debuggee
+            .send(format!("{{\"n\":{}}}", i).as_bytes())
+            .unwrap();
+        if i % 100 == 99 {
+            // Drain periodically so the ring wraps around
+            for j in (i - 99)..=i {
+                let frame = debugger.recv(Duration::from_millis(100)).unwrap();
+                assert_eq!(frame.unwrap(), format!("{{\"n\":{}}}", j).into_bytes());
+            }
+        }
+    }
+
+    assert!(debugger.recv(Duration::ZERO).unwrap().is_none());
+    assert_eq!(debugger.dropped_frames(), 0);
+
+    std::fs::remove_dir_all(&rendezvous.dir).unwrap();
+}
+
+#[test]
+fn test_mmap_ring_buffer_drop_policy_counts_drops() {
+    use dbug::communication::mmap::MmapTransport;
+    use dbug::communication::Transport;
+    use std::time::Duration;
+
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Drop).unwrap();
+    let debugger = MmapTransport::create(&rendezvous).unwrap();
+    let mut debuggee = MmapTransport::attach(&rendezvous).unwrap();
+
+    // Nobody is reading, so the ring eventually fills up and frames get dropped
+    let frame = vec![b'x'; 4096];
+    for _ in 0..64 {
+        debuggee.send(&frame).unwrap();
+    }
+
+    assert!(debuggee.dropped_outbound() > 0);
+    assert_eq!(debugger.dropped_frames(), debuggee.dropped_outbound());
+
+    // Frames that did fit are still delivered intact
+    let mut debugger = debugger;
+    let received = debugger.recv(Duration::from_millis(100)).unwrap();
+    assert_eq!(received.unwrap(), frame);
+
+    std::fs::remove_dir_all(&rendezvous.dir).unwrap();
+}
+
+#[test]
+fn test_handshake_negotiates_version_and_capabilities() {
+    use dbug::communication::{Capability, DebuggerMessage, PROTOCOL_VERSION};
+    use std::time::Duration;
+
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+
+    let debuggee_rendezvous = rendezvous.clone();
+    let debuggee = std::thread::spawn(move || {
+        let mut debuggee = CommunicationChannel::attach(&debuggee_rendezvous).unwrap();
+        let welcome = debuggee.handshake().unwrap();
+        (welcome, debuggee.has_capability(Capability::Stepping))
+    });
+
+    let message = debugger
+        .recv_event(Duration::from_secs(5))
+        .unwrap()
+        .map(|event| event.message);
+    match message {
+        Some(DebuggerMessage::Hello(hello)) => {
+            assert_eq!(hello.protocol_version, PROTOCOL_VERSION);
+            assert_eq!(hello.pid, std::process::id());
+        }
+        other => panic!("expected a Hello, got {:?}", other),
+    }
+
+    let (welcome, has_stepping) = debuggee.join().unwrap();
+    assert_eq!(welcome.protocol_version, PROTOCOL_VERSION);
+    assert!(has_stepping);
+    assert!(!welcome.capabilities.contains(&Capability::SetVariable));
+    assert_eq!(
+        debugger.peer().map(|hello| hello.pid),
+        Some(std::process::id())
+    );
+
+    debugger.close().unwrap();
+}
+
+#[test]
+fn test_handshake_refuses_incompatible_version() {
+    use dbug::communication::mmap::MmapTransport;
+    use dbug::communication::Transport;
+    use std::time::Duration;
+
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+    let mut debuggee = MmapTransport::attach(&rendezvous).unwrap();
+
+    // A debuggee from the future, with fields this build does not know about
+    let hello = r#"{"message":{"Hello":{"protocol_version":999,"pid":1,"future_field":true}}}"#;
+    debuggee.send(hello.as_bytes()).unwrap();
+
+    let result = debugger.recv_event(Duration::from_secs(5));
+    assert!(matches!(
+        result,
+        Err(dbug::DbugError::IncompatibleProtocol { debuggee: 999, .. })
+    ));
+
+    let response = debuggee.recv(Duration::from_secs(5)).unwrap().unwrap();
+    let response: DebuggerResponse = serde_json::from_slice(&response).unwrap();
+    assert!(matches!(response, DebuggerResponse::Refused { .. }));
+
+    debugger.close().unwrap();
+}
+
+fn assert_large_message_is_reassembled(kind: TransportKind) {
+    use dbug::communication::DebuggerMessage;
+    use std::time::Duration;
+
+    let rendezvous = SessionRendezvous::create(kind, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+
+    // Far larger than a single frame, and larger than the whole mmap ring
+    let value = format!("{:?}", (0..50_000).collect::<Vec<u32>>());
+    assert!(value.len() > 200 * 1024);
+
+    let debuggee_rendezvous = rendezvous.clone();
+    let sent = value.clone();
+    let debuggee = std::thread::spawn(move || {
+        let mut debuggee = CommunicationChannel::attach(&debuggee_rendezvous).unwrap();
+        debuggee.handshake().unwrap();
+        debuggee
+            .queue_message(DebuggerMessage::VariableChanged {
+                name: "numbers".to_string(),
+                type_name: "Vec<u32>".to_string(),
+                value: sent,
+                is_mutable: false,
+                structure: None,
+            })
+            .unwrap();
+        debuggee.close().unwrap();
+    });
+
+    let hello = debugger
+        .recv_event(Duration::from_secs(5))
+        .unwrap()
+        .map(|event| event.message);
+    assert!(matches!(hello, Some(DebuggerMessage::Hello(_))));
+
+    let message = debugger
+        .recv_event(Duration::from_secs(5))
+        .unwrap()
+        .map(|event| event.message);
+    match message {
+        Some(DebuggerMessage::VariableChanged {
+            value: received, ..
+        }) => {
+            assert_eq!(received, value)
+        }
+        other => panic!("expected the variable, got {:?}", other),
+    }
+
+    debuggee.join().unwrap();
+    debugger.close().unwrap();
+}
+
+#[test]
+fn test_mmap_transport_reassembles_large_messages() {
+    assert_large_message_is_reassembled(TransportKind::Mmap);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_transport_delivers_large_messages() {
+    assert_large_message_is_reassembled(TransportKind::UnixSocket);
+}
+
+/// Find a loopback address nothing is listening on yet
+fn free_loopback_address() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// Connect to a debuggee that may not be listening yet
+fn connect_with_retry(address: &str, token: &str) -> dbug::DbugResult<CommunicationChannel> {
+    for _ in 0..100 {
+        match CommunicationChannel::connect(address, token) {
+            Err(dbug::DbugError::CommunicationError(e)) if e.starts_with("Failed to connect") => {
+                std::thread::sleep(std::time::Duration::from_millis(20))
+            }
+            result => return result,
+        }
+    }
+    CommunicationChannel::connect(address, token)
+}
+
+#[test]
+fn test_tcp_transport_handshake_over_loopback() {
+    use dbug::communication::DebuggerMessage;
+    use std::time::Duration;
+
+    let address = free_loopback_address();
+    let rendezvous = SessionRendezvous::create(TransportKind::Tcp, BufferFullPolicy::Block)
+        .unwrap()
+        .listening_on(&address);
+
+    // The debuggee listens and blocks until an authenticated debugger connects
+    let debuggee_rendezvous = rendezvous.clone();
+    let debuggee = std::thread::spawn(move || {
+        let mut debuggee = CommunicationChannel::attach(&debuggee_rendezvous).unwrap();
+        debuggee.handshake().unwrap();
+        debuggee.wait_for_response().unwrap()
+    });
+
+    let mut debugger = connect_with_retry(&address, &rendezvous.token).unwrap();
+    let hello = debugger
+        .recv_event(Duration::from_secs(5))
+        .unwrap()
+        .map(|event| event.message);
+    assert!(matches!(hello, Some(DebuggerMessage::Hello(_))));
+
+    debugger.send_response(&DebuggerResponse::StepInto).unwrap();
+    let response = debuggee.join().unwrap();
+    assert!(matches!(response, Some(DebuggerResponse::StepInto)));
+
+    debugger.close().unwrap();
+    rendezvous.remove().unwrap();
+}
+
+#[test]
+fn test_tcp_transport_rejects_wrong_token() {
+    let address = free_loopback_address();
+    let rendezvous = SessionRendezvous::create(TransportKind::Tcp, BufferFullPolicy::Block)
+        .unwrap()
+        .listening_on(&address);
+
+    let debuggee_rendezvous = rendezvous.clone();
+    let debuggee =
+        std::thread::spawn(move || CommunicationChannel::attach(&debuggee_rendezvous).map(|_| ()));
+
+    // A connection without the token is turned away
+    let result = connect_with_retry(&address, "not-the-token");
+    match result {
+        Err(dbug::DbugError::CommunicationError(e)) => assert!(e.contains("rejected")),
+        other => panic!("expected the token to be rejected, got {:?}", other.err()),
+    }
+
+    // ...and the debuggee keeps waiting for the real debugger
+    let mut debugger = CommunicationChannel::connect(&address, &rendezvous.token).unwrap();
+    debuggee.join().unwrap().unwrap();
+
+    debugger.close().unwrap();
+    rendezvous.remove().unwrap();
+}
+
+#[test]
+fn test_tcp_transport_drops_stalled_connections() {
+    use std::io::Write;
+
+    let address = free_loopback_address();
+    let rendezvous = SessionRendezvous::create(TransportKind::Tcp, BufferFullPolicy::Block)
+        .unwrap()
+        .listening_on(&address);
+
+    let debuggee_rendezvous = rendezvous.clone();
+    let debuggee =
+        std::thread::spawn(move || CommunicationChannel::attach(&debuggee_rendezvous).map(|_| ()));
+
+    // A connection that starts a token frame and never finishes it...
+    let mut stalled = loop {
+        match std::net::TcpStream::connect(&address) {
+            Ok(stream) => break stream,
+            Err(_) => std::thread::sleep(std::time::Duration::from_millis(20)),
+        }
+    };
+    stalled.write_all(&[8]).unwrap();
+    std::thread::sleep(std::time::Duration::from_secs(1));
+
+    // ...is given up on, and the real debugger gets through
+    let mut debugger = CommunicationChannel::connect(&address, &rendezvous.token).unwrap();
+    debuggee.join().unwrap().unwrap();
+
+    drop(stalled);
+    debugger.close().unwrap();
+    rendezvous.remove().unwrap();
+}
+
+#[test]
+fn test_events_carry_thread_and_timestamp() {
+    use dbug::communication::{DebuggerMessage, ThreadInfo};
+    use std::time::Duration;
+
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+
+    let debuggee_rendezvous = rendezvous.clone();
+    let debuggee = std::thread::Builder::new()
+        .name("worker".to_string())
+        .spawn(move || {
+            let mut debuggee = CommunicationChannel::attach(&debuggee_rendezvous).unwrap();
+            debuggee.handshake().unwrap();
+            for function in ["outer", "inner"] {
+                debuggee
+                    .queue_message(DebuggerMessage::FunctionExited {
+                        function: function.to_string(),
+                        return_value: None,
+                    })
+                    .unwrap();
+            }
+            debuggee.close().unwrap();
+            ThreadInfo::current()
+        })
+        .unwrap();
+
+    debugger
+        .recv_event(Duration::from_secs(5))
+        .unwrap()
+        .unwrap();
+    let batch = debugger
+        .recv_event(Duration::from_secs(5))
+        .unwrap()
+        .unwrap();
+    let worker = debuggee.join().unwrap();
+
+    let events = batch.flatten();
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().all(|event| event.thread == worker));
+    assert_eq!(worker.name.as_deref(), Some("worker"));
+    assert_ne!(worker, ThreadInfo::current());
+    assert!(events[0].timestamp() <= events[1].timestamp());
+
+    debugger.close().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_event_pump_reports_messages_and_disconnect() {
+    use dbug::communication::{ChannelEvent, DebuggerMessage, EventPump};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::UnixSocket, BufferFullPolicy::Block).unwrap();
+    let debugger = Arc::new(Mutex::new(
+        CommunicationChannel::create(&rendezvous).unwrap(),
+    ));
+    let pump = EventPump::start(Arc::clone(&debugger));
+
+    let debuggee_rendezvous = rendezvous.clone();
+    let debuggee = std::thread::spawn(move || {
+        let mut debuggee = CommunicationChannel::attach(&debuggee_rendezvous).unwrap();
+        debuggee.handshake().unwrap();
+        for function in ["first", "second"] {
+            debuggee
+                .queue_message(DebuggerMessage::FunctionExited {
+                    function: function.to_string(),
+                    return_value: None,
+                })
+                .unwrap();
+        }
+        debuggee.close().unwrap();
+    });
+
+    let mut events = Vec::new();
+    while let Some(event) = pump.next_timeout(Duration::from_secs(5)) {
+        let disconnected = matches!(event, ChannelEvent::Disconnected);
+        events.push(event);
+        if disconnected {
+            break;
+        }
+    }
+    debuggee.join().unwrap();
+
+    assert!(matches!(events[0], ChannelEvent::Connected(_)));
+    let functions: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            ChannelEvent::Message(event) => match &event.message {
+                DebuggerMessage::FunctionExited { function, .. } => Some(function.as_str()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    assert_eq!(functions, ["first", "second"]);
+    assert!(matches!(events.last(), Some(ChannelEvent::Disconnected)));
+
+    drop(pump);
+    debugger.lock().unwrap().close().unwrap();
+}
+
+#[test]
+fn test_event_pump_reports_decode_errors() {
+    use dbug::communication::mmap::MmapTransport;
+    use dbug::communication::{
+        ChannelEvent, DebuggerEvent, DebuggerMessage, EventPump, Hello, Transport,
+    };
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Block).unwrap();
+    let debugger = Arc::new(Mutex::new(
+        CommunicationChannel::create(&rendezvous).unwrap(),
+    ));
+    let mut debuggee = MmapTransport::attach(&rendezvous).unwrap();
+    let pump = EventPump::start(Arc::clone(&debugger));
+
+    let hello = DebuggerEvent::new(DebuggerMessage::Hello(Hello::current()));
+    debuggee.send(&serde_json::to_vec(&hello).unwrap()).unwrap();
+    debuggee.send(b"definitely not json").unwrap();
+
+    let connected = pump.next_timeout(Duration::from_secs(5));
+    assert!(matches!(connected, Some(ChannelEvent::Connected(_))));
+    let garbage = pump.next_timeout(Duration::from_secs(5));
+    assert!(matches!(garbage, Some(ChannelEvent::DecodeError(_))));
+
+    drop(pump);
+    debugger.lock().unwrap().close().unwrap();
+}
+
+#[test]
+fn test_event_pump_stops_on_a_corrupt_ring() {
+    use dbug::communication::{ChannelEvent, EventPump};
+    use std::io::{Seek, SeekFrom, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::Mmap, BufferFullPolicy::Block).unwrap();
+    let debugger = Arc::new(Mutex::new(
+        CommunicationChannel::create(&rendezvous).unwrap(),
+    ));
+
+    // Publish 100 bytes holding a frame that claims to be longer
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(rendezvous.message_file_path())
+        .unwrap();
+    file.seek(SeekFrom::Start(64)).unwrap();
+    file.write_all(&1000u32.to_le_bytes()).unwrap();
+    file.seek(SeekFrom::Start(16)).unwrap();
+    file.write_all(&100u64.to_le_bytes()).unwrap();
+    file.flush().unwrap();
+
+    let pump = EventPump::start(Arc::clone(&debugger));
+    let error = pump.next_timeout(Duration::from_secs(5));
+    assert!(matches!(error, Some(ChannelEvent::Error(_))), "{:?}", error);
+    let end = pump.next_timeout(Duration::from_secs(5));
+    assert!(matches!(end, Some(ChannelEvent::Disconnected)), "{:?}", end);
+    assert!(pump.next_timeout(Duration::from_millis(200)).is_none());
+    assert!(!debugger.lock().unwrap().is_active());
+}
+
+#[test]
+fn test_trace_round_trip() {
+    use dbug::communication::{DebuggerEvent, DebuggerMessage, Hello, Trace, TraceRecorder};
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("session.dbugtrace");
+
+    let mut recorder = TraceRecorder::create(&path).unwrap();
+    recorder.record_connected(&Hello::current()).unwrap();
+    recorder
+        .record_event(&DebuggerEvent::new(DebuggerMessage::FunctionEntered {
+            function: "main".to_string(),
+            file: "src/main.rs".to_string(),
+            line: 3,
+        }))
+        .unwrap();
+    recorder
+        .record_event(&DebuggerEvent::new(DebuggerMessage::VariableChanged {
+            name: "count".to_string(),
+            type_name: "usize".to_string(),
+            value: "2".to_string(),
+            is_mutable: false,
+            structure: None,
+        }))
+        .unwrap();
+    drop(recorder);
+
+    // A recorder that died mid-write leaves half a line behind
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    write!(file, "{{\"record\":\"event\",\"event\":{{\"thr").unwrap();
+    drop(file);
+
+    let trace = Trace::load(&path).unwrap();
+    assert_eq!(trace.hello, Some(Hello::current()));
+    assert_eq!(trace.events.len(), 2);
+    assert!(matches!(
+        &trace.events[0].message,
+        DebuggerMessage::FunctionEntered { function, line: 3, .. } if function == "main"
+    ));
+    assert!(trace.events[0].timestamp_us <= trace.events[1].timestamp_us);
+}
+
+#[test]
+fn test_breakpoint_table_is_pushed_during_handshake() {
+    use dbug::communication::BreakpointSpec;
+    use dbug::runtime::flow_control::should_break_at;
+    use std::time::Duration;
+
+    let rendezvous =
+        SessionRendezvous::create(TransportKind::UnixSocket, BufferFullPolicy::Block).unwrap();
+    let mut debugger = CommunicationChannel::create(&rendezvous).unwrap();
+
+    let mut every_other = BreakpointSpec::new("src/sync.rs", 20, true);
+    every_other.hit_count = Some("% 2".to_string());
+    debugger
+        .set_breakpoints(vec![
+            BreakpointSpec::new("src/sync.rs", 10, false),
+            every_other,
+        ])
+        .unwrap();
+
+    let debuggee = std::thread::spawn(move || {
+        let mut debuggee = CommunicationChannel::attach(&rendezvous).unwrap();
+        debuggee.handshake().unwrap();
+    });
+    debugger.recv_event(Duration::from_secs(5)).unwrap();
+    debuggee.join().unwrap();
+
+    // Decided locally from the table the handshake installed
+    assert!(!should_break_at("src/sync.rs", 10));
+    assert!(should_break_at("src/sync.rs", 30));
+    assert!(!should_break_at("src/sync.rs", 20));
+    assert!(should_break_at("src/sync.rs", 20));
+
+    debugger.close().unwrap();
+}
+
+#[test]
+fn test_function_exit_return_value_is_optional_on_the_wire() {
+    use dbug::communication::DebuggerMessage;
+
+    // Debuggees built before return values were captured leave the field out
+    let message: DebuggerMessage =
+        serde_json::from_str(r#"{"FunctionExited":{"function":"old"}}"#).unwrap();
+    assert!(matches!(
+        message,
+        DebuggerMessage::FunctionExited { function, return_value: None } if function == "old"
+    ));
+
+    let message = DebuggerMessage::FunctionExited {
+        function: "new".to_string(),
+        return_value: Some("Ok(12)".to_string()),
+    };
+    let json = serde_json::to_string(&message).unwrap();
+    assert!(matches!(
+        serde_json::from_str(&json).unwrap(),
+        DebuggerMessage::FunctionExited { return_value: Some(value), .. } if value == "Ok(12)"
+    ));
+}
@@ -0,0 +1,63 @@
+//! Tests for the function names reported at breakpoints
+
+use dbug::runtime::flow_control::function_stack;
+
+mod parser {
+    use dbug::prelude::*;
+
+    #[dbug]
+    pub fn parse(input: &str) -> Vec<String> {
+        input
+            .split(',')
+            .map(|_| token())
+            .next_back()
+            .unwrap_or_default()
+    }
+
+    #[dbug]
+    fn token() -> Vec<String> {
+        break_when!(false, label = "token");
+        dbug::runtime::flow_control::function_stack()
+    }
+
+    pub struct Buffer<T>(pub T);
+
+    #[dbug]
+    impl Buffer<u8> {
+        pub fn top(&self) -> Vec<String> {
+            dbug::runtime::flow_control::function_stack()
+        }
+    }
+
+    #[dbug]
+    impl<T> Buffer<Vec<T>> {
+        pub fn top(&self) -> Vec<String> {
+            dbug::runtime::flow_control::function_stack()
+        }
+    }
+}
+
+#[test]
+fn test_call_stack_has_function_paths() {
+    // Act as if started by the debugger, so the hooks keep the stack
+    std::env::set_var(dbug::communication::ENABLED_ENV, "1");
+
+    assert_eq!(
+        parser::parse("a,b"),
+        [
+            "function_names_test::parser::token",
+            "function_names_test::parser::parse"
+        ]
+    );
+    assert!(function_stack().is_empty());
+
+    // Methods of generic types are told apart by the type's arguments
+    assert_eq!(
+        parser::Buffer(1u8).top(),
+        ["function_names_test::parser::Buffer<u8>::top"]
+    );
+    assert_eq!(
+        parser::Buffer(vec!['a']).top(),
+        ["function_names_test::parser::Buffer<Vec<T>>::top"]
+    );
+}
@@ -0,0 +1,44 @@
+//! Tests for logpoints
+
+use dbug::communication::BreakpointSpec;
+use dbug::prelude::*;
+use dbug::runtime::flow_control::{install_breakpoints, should_break_at};
+use dbug::runtime::{format_log_message, VariableInspector};
+
+#[test]
+fn test_log_message_template_uses_variable_values() {
+    let mut variables = VariableInspector::new();
+    for (name, value) in [("user", "42"), ("name", "\"ada\""), ("ok", "true")] {
+        variables.register_variable(Variable::new(
+            name,
+            "?",
+            VariableValue::from_debug(value),
+            0,
+            false,
+        ));
+    }
+
+    assert_eq!(
+        format_log_message("user={user} name={ name } ok={ok} {{literal}}", &variables),
+        "user=42 name=\"ada\" ok=true {literal}"
+    );
+}
+
+#[test]
+fn test_logpoints_do_not_stop() {
+    install_breakpoints(&[
+        BreakpointSpec::logpoint("src/lib.rs", 7, "x is {x}"),
+        BreakpointSpec::new("src/lib.rs", 8, true),
+    ]);
+
+    assert!(!should_break_at("src/lib.rs", 7));
+    assert!(should_break_at("src/lib.rs", 8));
+
+    // Without a debugger the macro is a no-op, and its arguments aren't evaluated
+    let total = 3;
+    log_point!("total={} next={}", total, not_formatted());
+}
+
+fn not_formatted() -> u32 {
+    panic!("log_point! formatted its message without a debugger")
+}
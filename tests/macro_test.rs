@@ -0,0 +1,342 @@
+//! Tests for the instrumentation macros
+
+use dbug::prelude::*;
+
+#[derive(Debug)]
+struct Parser {
+    input: String,
+}
+
+impl Parser {
+    #[dbug(args, ret)]
+    fn first_word(&self) -> &str {
+        self.input.split_whitespace().next().unwrap_or("")
+    }
+
+    #[dbug(args, ret)]
+    fn into_words(self, limit: usize) -> Vec<String> {
+        self.input
+            .split_whitespace()
+            .take(limit)
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[dbug(args, ret)]
+fn parse_sum(mut total: i64, items: &[&str]) -> Result<i64, std::num::ParseIntError> {
+    for item in items {
+        if item.is_empty() {
+            return Ok(total);
+        }
+        total += item.parse::<i64>()?;
+    }
+    total *= 2;
+    Ok(total)
+}
+
+#[dbug(args)]
+fn describe((name, count): (&str, u32), verbose: bool) -> String {
+    if verbose {
+        format!("{} x{}", name, count)
+    } else {
+        name.to_string()
+    }
+}
+
+struct Celsius(u8);
+
+impl From<Celsius> for u32 {
+    fn from(value: Celsius) -> u32 {
+        value.0.into()
+    }
+}
+
+// Generic arguments have no `Debug` to capture; they show their type name
+#[dbug(args)]
+fn widen<T: Into<u32>>(value: T, offset: u32) -> u32 {
+    value.into() + offset
+}
+
+#[test]
+fn test_args_and_ret_keep_function_behavior() {
+    // Early returns and `?` still leave the function with the right value
+    assert_eq!(parse_sum(1, &["2", "3"]), Ok(12));
+    assert_eq!(parse_sum(1, &["2", "", "3"]), Ok(3));
+    assert!(parse_sum(0, &["x"]).is_err());
+
+    // Destructured arguments are left alone
+    assert_eq!(describe(("apple", 3), true), "apple x3");
+
+    assert_eq!(widen(Celsius(20), 1), 21);
+    assert_eq!(widen(7u8, 0), 7);
+
+    // Methods can borrow from or consume `self`
+    let parser = Parser {
+        input: "hello big world".to_string(),
+    };
+    assert_eq!(parser.first_word(), "hello");
+    assert_eq!(parser.into_words(2), ["hello", "big"]);
+}
+
+trait Shape {
+    fn area(&self) -> f64;
+}
+
+struct Square {
+    side: f64,
+}
+
+#[dbug(ret)]
+impl Shape for Square {
+    fn area(&self) -> f64 {
+        self.side * self.side
+    }
+}
+
+#[dbug(args)]
+mod geometry {
+    pub struct Callback(pub fn(u32) -> u32);
+
+    pub const fn unit() -> u32 {
+        1
+    }
+
+    pub fn scale(value: u32, factor: u32) -> u32 {
+        value * factor
+    }
+
+    #[dbug(skip)]
+    pub fn apply(callback: Callback, value: u32) -> u32 {
+        (callback.0)(value)
+    }
+
+    impl Callback {
+        #[dbug(ret)]
+        pub fn call_twice(&self, value: u32) -> u32 {
+            (self.0)((self.0)(value))
+        }
+
+        // Generic arguments are shown by their type name
+        pub fn call_with<T: Into<u32>>(&self, value: T) -> u32 {
+            (self.0)(value.into())
+        }
+
+        pub fn evens(&self, limit: u32) -> impl Iterator<Item = u32> + '_ {
+            (0..limit)
+                .map(|value| (self.0)(value))
+                .filter(|value| value % 2 == 0)
+        }
+    }
+}
+
+#[test]
+fn test_impl_blocks_and_modules_are_instrumented() {
+    use geometry::Callback;
+
+    assert_eq!(Square { side: 3.0 }.area(), 9.0);
+
+    // The const fn is left alone, so it still works in a const context
+    const UNIT: u32 = geometry::unit();
+    assert_eq!(geometry::scale(UNIT, 4), 4);
+
+    // `skip` leaves `apply` uninstrumented
+    assert_eq!(geometry::apply(Callback(|v| v + 1), 1), 2);
+    assert_eq!(Callback(|v| v * 3).call_twice(2), 18);
+    assert_eq!(Callback(|v| v + 2).call_with(3u8), 5);
+    assert_eq!(Callback(|v| v).evens(5).collect::<Vec<_>>(), [0, 2, 4]);
+}
+
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[dbug(locals)]
+fn trace_locals(values: &[i32]) -> Option<i32> {
+    let mut total = 0;
+    let (first, rest) = values.split_first()?;
+    let Point { x, y: height } = Point { x: *first, y: 2 };
+    let Some(last) = rest.last() else {
+        return Some(x * height);
+    };
+
+    // Closures have no `Debug`; they are shown by type instead
+    let add = |a: i32, b: i32| a + b;
+    for value in rest {
+        let doubled = add(*value, *value);
+        total += doubled;
+    }
+
+    fn helper(value: i32) -> i32 {
+        let inner = value + 1;
+        inner * 2
+    }
+
+    // Nothing to register until it's assigned
+    #[allow(clippy::needless_late_init)]
+    let declared_later;
+    declared_later = helper(*last);
+    Some(total + declared_later)
+}
+
+#[test]
+fn test_locals_are_traced_without_changing_behavior() {
+    assert_eq!(trace_locals(&[]), None);
+    assert_eq!(trace_locals(&[3]), Some(6));
+    assert_eq!(trace_locals(&[1, 2, 3]), Some(18));
+}
+
+#[test]
+fn test_breakpoints_take_labels_and_conditions() {
+    let mut checked = 0;
+    let mut check = |result: bool| {
+        checked += 1;
+        result
+    };
+
+    for attempts in 0..5 {
+        break_here!(label = "retry", if check(attempts > 3));
+        break_when!(check(attempts == 0), label = "first attempt");
+        break_when!(attempts == 2);
+    }
+    break_here!(label = "done");
+    break_here!();
+
+    assert_eq!(checked, 10);
+}
+
+#[derive(Debug, Default)]
+struct State {
+    count: usize,
+}
+
+#[derive(Debug, Default)]
+struct Worker {
+    state: State,
+    items: Vec<u8>,
+}
+
+impl Worker {
+    fn inspect(&mut self, limit: usize) -> usize {
+        let mut seen = 0;
+        register_var!(limit, mut seen, self.state.count, self.items[0] as "first");
+        register_var!(self.items.len() as "len": "usize", mut self.state as "state",);
+        register_var!(std::collections::HashMap::<u8, usize>::new() as "index", limit);
+
+        seen += self.items.len();
+        self.state.count += 1;
+        seen.min(limit)
+    }
+}
+
+#[test]
+fn test_register_var_takes_expressions_and_overrides() {
+    let mut worker = Worker {
+        items: vec![7, 8, 9],
+        ..Worker::default()
+    };
+
+    assert_eq!(worker.inspect(2), 2);
+    assert_eq!(worker.state.count, 1);
+}
+
+#[derive(DbugInspect)]
+struct Inventory<T> {
+    name: &'static str,
+    items: Vec<T>,
+    #[inspect(debug)]
+    origin: std::net::Ipv4Addr,
+    #[inspect(debug)]
+    fill: f32,
+    #[inspect(skip)]
+    _callback: fn(),
+}
+
+#[derive(DbugInspect)]
+enum Slot {
+    Empty,
+    Filled(u32, Option<char>),
+    Reserved { by: String },
+}
+
+#[test]
+fn test_derived_inspect_builds_a_structure() {
+    let mut items = Vec::with_capacity(8);
+    items.extend([Slot::Empty, Slot::Filled(3, Some('x'))]);
+    let inventory = Inventory {
+        name: "shelf",
+        items,
+        origin: std::net::Ipv4Addr::LOCALHOST,
+        fill: f32::NAN,
+        _callback: || {},
+    };
+
+    let fields = match inventory.to_variable_value() {
+        VariableValue::Struct(fields) => fields,
+        other => panic!("expected a struct, got {}", other),
+    };
+    assert_eq!(fields.len(), 4, "skipped fields are left out");
+    assert!(matches!(&fields["name"], VariableValue::String(name) if name == "shelf"));
+    assert_eq!(fields["origin"].to_string(), "\"127.0.0.1\"");
+    assert!(matches!(&fields["fill"], VariableValue::String(fill) if fill == "NaN"));
+    match &fields["items"] {
+        VariableValue::Vec {
+            elements,
+            length: 2,
+            capacity: 8,
+        } => {
+            assert_eq!(elements[0].to_string(), "Slot{ Empty }");
+            assert_eq!(
+                elements[1].to_string(),
+                "Slot{ Filled } {0: 3, 1: Some('x')}"
+            );
+        }
+        other => panic!("expected a vec, got {}", other),
+    }
+
+    let reserved = Slot::Reserved {
+        by: "me".to_string(),
+    };
+    assert_eq!(
+        reserved.to_variable_value().to_string(),
+        "Slot{ Reserved } {by: \"me\"}"
+    );
+
+    // `register_var!` prefers the structure, and doesn't need `Debug` for it
+    register_var!(inventory, reserved);
+}
+
+struct Buffer<const N: usize>([u8; N]);
+
+#[dbug(locals)]
+fn const_contexts() -> usize {
+    const LEN: usize = {
+        let n = 2;
+        n * 2
+    };
+    let zeros = [0u8; {
+        let n = 1;
+        n + 2
+    }];
+    let table: [u8; {
+        let n = 1;
+        n * 2
+    }] = [1; 2];
+    let inline = const {
+        let x = 2;
+        x + 3
+    };
+    let buffer = Buffer::<
+        {
+            let n = 1;
+            n + 1
+        },
+    >([0; 2]);
+    LEN + zeros.len() + table.len() + inline + buffer.0.len()
+}
+
+#[test]
+fn test_const_contexts_are_left_alone() {
+    assert_eq!(const_contexts(), 4 + 3 + 2 + 5 + 2);
+}
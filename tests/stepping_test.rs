@@ -0,0 +1,56 @@
+//! Tests for deciding where stepping stops
+
+use dbug::communication::BreakpointSpec;
+use dbug::runtime::flow_control::{
+    enter_call, exit_call, install_breakpoints, mark_stop, should_break_at,
+    should_stop_at_checkpoint, step_ends_here,
+};
+use dbug::runtime::{set_continue, set_step_into, set_step_out, set_step_over};
+
+#[test]
+fn test_steps_end_relative_to_the_last_stop() {
+    // Stopped one call deep
+    enter_call();
+    mark_stop();
+
+    set_step_over().unwrap();
+    assert!(step_ends_here());
+    enter_call();
+    assert!(!step_ends_here(), "step over must not stop inside a callee");
+    exit_call();
+    assert!(step_ends_here());
+
+    set_step_into().unwrap();
+    enter_call();
+    assert!(step_ends_here());
+    exit_call();
+
+    set_step_out().unwrap();
+    assert!(!step_ends_here());
+    exit_call();
+    assert!(step_ends_here());
+
+    // Other threads keep running while this one steps
+    std::thread::spawn(|| assert!(!step_ends_here()))
+        .join()
+        .unwrap();
+
+    // Without stepping, only lines the debugger set a breakpoint on stop
+    set_continue().unwrap();
+    assert!(!should_stop_at_checkpoint("src/main.rs", 10));
+    install_breakpoints(&[
+        BreakpointSpec::new("src/main.rs", 10, true),
+        BreakpointSpec::new("src/main.rs", 11, false),
+    ]);
+    assert!(should_stop_at_checkpoint("src/main.rs", 10));
+    assert!(!should_stop_at_checkpoint("src/main.rs", 11));
+    assert!(!should_stop_at_checkpoint("src/main.rs", 12));
+
+    // Debug points on other threads only stop where their breakpoints say
+    set_step_over().unwrap();
+    assert!(should_break_at("src/main.rs", 11));
+    std::thread::spawn(|| assert!(!should_break_at("src/main.rs", 11)))
+        .join()
+        .unwrap();
+    set_continue().unwrap();
+}
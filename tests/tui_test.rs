@@ -0,0 +1,193 @@
+//! Tests for the TUI application state
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use dbug::communication::{
+    BreakpointSpec, BufferFullPolicy, ChannelEvent, CommunicationChannel, DebuggerEvent,
+    DebuggerMessage, DebuggerResponse, TransportKind,
+};
+use dbug::tui::app::{App, DebugState, PanelType};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The tests share the global communication channel
+static CHANNEL: Mutex<()> = Mutex::new(());
+
+fn press(app: &mut App, code: KeyCode) {
+    app.handle_event(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+}
+
+#[test]
+fn test_ctrl_c_pauses_a_program_inside_a_function() {
+    let _channel = CHANNEL.lock().unwrap_or_else(|e| e.into_inner());
+    let rendezvous =
+        dbug::communication::init_debugging_session(TransportKind::Mmap, BufferFullPolicy::Block)
+            .unwrap();
+    let pump = dbug::communication::start_event_pump();
+
+    // The program connects and then waits for a pause request
+    let debuggee = std::thread::spawn(move || {
+        let mut debuggee = CommunicationChannel::attach(&rendezvous).unwrap();
+        debuggee.handshake().unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if let Some(request) = debuggee.poll_request().unwrap() {
+                return Some(request);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        None
+    });
+
+    let hello = match pump.next_timeout(Duration::from_secs(5)) {
+        Some(ChannelEvent::Connected(hello)) => hello,
+        other => panic!("expected the program to connect, got {:?}", other),
+    };
+
+    let mut app = App::new();
+    app.connected(&hello);
+    app.apply_event(&DebuggerEvent::new(DebuggerMessage::FunctionEntered {
+        function: "app::busy_loop".to_string(),
+        file: "src/main.rs".to_string(),
+        line: 3,
+    }));
+    assert!(matches!(app.debug_state, DebugState::Running));
+
+    app.handle_event(Event::Key(KeyEvent::new(
+        KeyCode::Char('c'),
+        KeyModifiers::CONTROL,
+    )));
+    assert!(matches!(
+        debuggee.join().unwrap(),
+        Some(DebuggerResponse::Pause)
+    ));
+    assert!(app.console.iter().any(|line| line == "Pausing..."));
+
+    drop(pump);
+    dbug::communication::cleanup_debugging_session().unwrap();
+}
+
+#[test]
+fn test_stepping_leaves_the_paused_state() {
+    let _channel = CHANNEL.lock().unwrap_or_else(|e| e.into_inner());
+
+    let rendezvous =
+        dbug::communication::init_debugging_session(TransportKind::Mmap, BufferFullPolicy::Block)
+            .unwrap();
+    let pump = dbug::communication::start_event_pump();
+
+    // The program connects and then waits at a breakpoint
+    let debuggee = std::thread::spawn(move || {
+        let mut debuggee = CommunicationChannel::attach(&rendezvous).unwrap();
+        debuggee.handshake().unwrap();
+        debuggee.wait_for_response().unwrap()
+    });
+
+    let hello = match pump.next_timeout(Duration::from_secs(5)) {
+        Some(ChannelEvent::Connected(hello)) => hello,
+        other => panic!("expected the program to connect, got {:?}", other),
+    };
+
+    let mut app = App::new();
+    app.connected(&hello);
+    app.breakpoint_hit("src/main.rs".to_string(), 5, "app::main".to_string());
+
+    press(&mut app, KeyCode::F(10));
+    assert!(matches!(
+        debuggee.join().unwrap(),
+        Some(DebuggerResponse::StepOver)
+    ));
+    assert!(matches!(app.debug_state, DebugState::Running));
+
+    drop(pump);
+    dbug::communication::cleanup_debugging_session().unwrap();
+}
+
+#[test]
+fn test_typing_a_log_message_turns_the_last_stop_into_a_logpoint() {
+    let _channel = CHANNEL.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut app = App::new();
+    app.breakpoint_hit("src/main.rs".to_string(), 12, "app::main".to_string());
+
+    press(&mut app, KeyCode::Char('l'));
+    for c in "x is {x}q".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    press(&mut app, KeyCode::Backspace);
+    assert!(app.running);
+    press(&mut app, KeyCode::Enter);
+
+    assert!(app.prompt.is_none());
+    assert_eq!(app.breakpoints.len(), 1);
+    assert_eq!(app.breakpoints[0].log_message.as_deref(), Some("x is {x}"));
+    assert!(app.breakpoints[0].enabled);
+
+    // An empty message turns it back into a breakpoint
+    press(&mut app, KeyCode::Char('l'));
+    assert_eq!(app.prompt.as_ref().unwrap().text, "x is {x}");
+    for _ in 0..8 {
+        press(&mut app, KeyCode::Backspace);
+    }
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(app.breakpoints[0].log_message, None);
+
+    dbug::communication::set_breakpoints(Vec::new()).unwrap();
+}
+
+#[test]
+fn test_breakpoints_panel_sets_conditions_and_hit_counts() {
+    let _channel = CHANNEL.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut app = App::new();
+    app.breakpoints = vec![
+        BreakpointSpec::new("src/main.rs", 3, true),
+        BreakpointSpec::new("src/main.rs", 9, true),
+    ];
+    app.active_panel = PanelType::Breakpoints;
+    press(&mut app, KeyCode::Down);
+
+    let type_setting = |app: &mut App, key: char, text: &str| {
+        press(app, KeyCode::Char(key));
+        while !app.prompt.as_ref().unwrap().text.is_empty() {
+            press(app, KeyCode::Backspace);
+        }
+        for c in text.chars() {
+            press(app, KeyCode::Char(c));
+        }
+        press(app, KeyCode::Enter);
+    };
+    type_setting(&mut app, 'c', "x > 10");
+    type_setting(&mut app, 'h', "% 2");
+    type_setting(&mut app, 'h', "every other");
+
+    assert_eq!(app.breakpoints[0].condition, None);
+    assert_eq!(app.breakpoints[1].condition.as_deref(), Some("x > 10"));
+    assert_eq!(app.breakpoints[1].hit_count.as_deref(), Some("% 2"));
+    assert!(app
+        .console
+        .last()
+        .unwrap()
+        .starts_with("Invalid hit count 'every other'"));
+
+    dbug::communication::set_breakpoints(Vec::new()).unwrap();
+}
+
+#[test]
+fn test_async_breakpoint_pauses_the_app() {
+    let mut app = App::new();
+    app.apply_event(&DebuggerEvent::new(DebuggerMessage::AsyncBreakPoint {
+        file: "src/worker.rs".to_string(),
+        line: 14,
+        column: 9,
+        task_id: 3,
+        function: "app::worker::fetch".to_string(),
+    }));
+
+    assert!(matches!(
+        &app.debug_state,
+        DebugState::Paused { file, line: 14, function }
+            if file == "src/worker.rs" && function == "app::worker::fetch"
+    ));
+    assert_eq!(app.last_stop, Some(("src/worker.rs".to_string(), 14)));
+}